@@ -2,26 +2,65 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use tari::liquidity::Position;
 
+use crate::timelock_policy::TimelockConfig;
+use crate::token::{Token, TokenRegistry};
+
+pub const MAX_SPREAD_BPS: u64 = 10_000;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub network_address: String,
+    // required by the operator JSON-RPC methods (e.g. "list_swaps"), which are disabled without it
+    pub admin_token: Option<String>,
+    // if not specified, swaps will only be kept in memory and lost on restarts
+    pub database_path: Option<String>,
     pub ethereum: EthereumConfig,
     pub tari: TariConfig,
-    pub positions: Vec<Position>,
+    // extra tokens to the native ones of each network
+    #[serde(default)]
+    pub tokens: Vec<Token>,
+    #[serde(default)]
+    pub timelocks: TimelockConfig,
+    #[serde(default)]
+    pub proposals: ProposalConfig,
+    // how long the clients can use our quotes
+    #[serde(default = "default_quote_ttl_secs")]
+    pub quote_ttl_secs: u64,
+    // quotes that can be issued and not used yet at the same time
+    #[serde(default = "default_max_quotes")]
+    pub max_quotes: usize,
+    pub positions: Vec<PositionConfig>,
+}
+
+// The ratio between the position balances defines the price at which we are willing to swap
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionConfig {
+    #[serde(flatten)]
+    pub position: Position,
+    // limits of the amount of provided token that a single swap can take from the position
+    pub min_fill: Option<u64>,
+    pub max_fill: Option<u64>,
+    // margin (in basis points) applied over the position price in each swap
+    #[serde(default)]
+    pub spread_bps: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EthereumConfig {
     pub rpc_url: String,
     pub private_key: String,
-    pub account_address: String,
     pub contract_address: String,
+    // the HashedTimelockERC20 contract, only needed when swapping ERC20 tokens
+    pub erc20_contract_address: Option<String>,
+    // the HashedTimelockERC721 contract, only needed when swapping NFTs
+    pub erc721_contract_address: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TariConfig {
-    pub account_component: String,
     pub public_key: String,
+    // the secret of "public_key", used to sign the quotes, which are disabled without it
+    pub private_key: Option<String>,
     pub public_key_index: u64,
     pub wallet_endpoint: String,
     pub wallet_token: String,
@@ -29,12 +68,219 @@ pub struct TariConfig {
     pub liquidity_component: String,
 }
 
+// Limits to the swaps that the clients propose but never fund
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProposalConfig {
+    // time that the clients have to lock their funds once we accept their proposal
+    pub lock_deadline_secs: u64,
+    // proposals of the same client address that can be waiting for their funds at the same time
+    pub max_open_per_client: usize,
+    // proposals of all the clients that can be waiting for their funds at the same time
+    pub max_open: usize,
+    // the expired proposals are kept for a while, so the clients can still query their status
+    pub expired_retention_secs: u64,
+}
+
+impl Default for ProposalConfig {
+    fn default() -> Self {
+        Self {
+            lock_deadline_secs: 600,
+            max_open_per_client: 5,
+            max_open: 100,
+            expired_retention_secs: 86400,
+        }
+    }
+}
+
+impl ProposalConfig {
+    fn validate(&self) -> Result<(), String> {
+        if self.lock_deadline_secs == 0 {
+            return Err("lock_deadline_secs must be greater than zero".to_string());
+        }
+        if self.max_open_per_client == 0 {
+            return Err("max_open_per_client must be greater than zero".to_string());
+        }
+        if self.max_open < self.max_open_per_client {
+            return Err("max_open must be at least max_open_per_client".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn default_quote_ttl_secs() -> u64 {
+    60
+}
+
+fn default_max_quotes() -> usize {
+    1000
+}
+
 impl Config {
     pub fn read(path: String) -> Self {
         let content = fs::read_to_string(&path)
             .unwrap_or_else(|_| panic!("Unable to read config file '{}'", path));
 
-        serde_json::from_str(&content)
-            .unwrap_or_else(|e| panic!("'{}' file does not have a valid JSON format: {}", path, e))
+        let config: Self = serde_json::from_str(&content)
+            .unwrap_or_else(|e| panic!("'{}' file does not have a valid JSON format: {}", path, e));
+
+        config
+            .timelocks
+            .validate()
+            .unwrap_or_else(|e| panic!("Invalid timelocks: {}", e));
+        config
+            .proposals
+            .validate()
+            .unwrap_or_else(|e| panic!("Invalid proposals: {}", e));
+        if config.max_quotes == 0 {
+            panic!("Invalid max_quotes: it must be greater than zero");
+        }
+
+        let token_registry = config
+            .token_registry()
+            .unwrap_or_else(|e| panic!("Invalid tokens: {}", e));
+        for position in &config.positions {
+            position
+                .validate(&token_registry)
+                .unwrap_or_else(|e| panic!("Invalid position {:?}: {}", position.position, e));
+        }
+
+        config
+    }
+
+    pub fn token_registry(&self) -> Result<TokenRegistry, anyhow::Error> {
+        TokenRegistry::new(self.tokens.clone())
+    }
+}
+
+impl PositionConfig {
+    fn validate(&self, token_registry: &TokenRegistry) -> Result<(), String> {
+        for (token, balance) in [
+            (
+                &self.position.provided_token,
+                self.position.provided_token_balance,
+            ),
+            (
+                &self.position.requested_token,
+                self.position.requested_token_balance,
+            ),
+        ] {
+            let token = token_registry.get(token).map_err(|e| e.to_string())?;
+            if token.is_non_fungible() && balance != 1 {
+                return Err(format!("the balance of the NFT '{}' must be one", token.id));
+            }
+        }
+        if self.position.provided_token_balance == 0 || self.position.requested_token_balance == 0 {
+            return Err("the position balances must be greater than zero".to_string());
+        }
+        if let (Some(min_fill), Some(max_fill)) = (self.min_fill, self.max_fill) {
+            if min_fill > max_fill {
+                return Err("min_fill cannot be greater than max_fill".to_string());
+            }
+        }
+        if self.spread_bps >= MAX_SPREAD_BPS {
+            return Err(format!("spread_bps must be lower than {}", MAX_SPREAD_BPS));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::{AssetKind, Network};
+
+    fn registry() -> TokenRegistry {
+        TokenRegistry::new(vec![Token {
+            id: "punk".to_string(),
+            network: Network::Ethereum,
+            kind: AssetKind::Erc721 {
+                contract_address: "0x5fbdb2315678afecb367f032d93f642f64180aa3".to_string(),
+                token_id: "42".to_string(),
+            },
+            decimals: 0,
+            symbol: "PUNK".to_string(),
+        }])
+        .unwrap()
+    }
+
+    fn parse_position(json: &str) -> PositionConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn it_parses_the_positions() {
+        let position = parse_position(
+            r#"{
+                "provided_token": "tari",
+                "provided_token_balance": 1000,
+                "requested_token": "eth.wei",
+                "requested_token_balance": 2000,
+                "min_fill": 10,
+                "spread_bps": 50
+            }"#,
+        );
+
+        assert_eq!(position.position.provided_token, "tari");
+        assert_eq!(position.position.provided_token_balance, 1000);
+        assert_eq!(position.position.requested_token, "eth.wei");
+        assert_eq!(position.position.requested_token_balance, 2000);
+        assert_eq!(position.min_fill, Some(10));
+        assert_eq!(position.max_fill, None);
+        assert_eq!(position.spread_bps, 50);
+        position.validate(&registry()).unwrap();
+    }
+
+    #[test]
+    fn it_rejects_positions_with_unknown_tokens() {
+        let position = parse_position(
+            r#"{
+                "provided_token": "tari",
+                "provided_token_balance": 1000,
+                "requested_token": "eth",
+                "requested_token_balance": 2000
+            }"#,
+        );
+        let err = position.validate(&registry()).unwrap_err();
+        assert!(err.contains("Unknown token 'eth'"));
+    }
+
+    #[test]
+    fn it_rejects_nft_positions_with_many_tokens() {
+        let position = parse_position(
+            r#"{
+                "provided_token": "punk",
+                "provided_token_balance": 2,
+                "requested_token": "eth.wei",
+                "requested_token_balance": 2000
+            }"#,
+        );
+        assert!(position.validate(&registry()).is_err());
+    }
+
+    #[test]
+    fn it_rejects_invalid_position_limits() {
+        let valid_position = parse_position(
+            r#"{
+                "provided_token": "tari",
+                "provided_token_balance": 1000,
+                "requested_token": "eth.wei",
+                "requested_token_balance": 2000
+            }"#,
+        );
+        valid_position.validate(&registry()).unwrap();
+
+        let mut position = valid_position.clone();
+        position.position.provided_token_balance = 0;
+        assert!(position.validate(&registry()).is_err());
+
+        let mut position = valid_position.clone();
+        position.min_fill = Some(100);
+        position.max_fill = Some(10);
+        assert!(position.validate(&registry()).is_err());
+
+        let mut position = valid_position;
+        position.spread_bps = MAX_SPREAD_BPS;
+        assert!(position.validate(&registry()).is_err());
     }
 }
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, ensure};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub struct TokenBalance {
+    // can be used to back new swaps
+    pub available: u64,
+    // locked in our contracts of pending swaps
+    pub reserved: u64,
+    // received from the clients in completed swaps
+    pub settled: u64,
+}
+
+// Keeps track of our balances per token, so we never commit more liquidity than we have
+#[derive(Debug, Clone, Default)]
+pub struct Inventory {
+    balances: HashMap<String, TokenBalance>,
+}
+
+impl Inventory {
+    pub fn new(available_balances: HashMap<String, u64>) -> Self {
+        let balances = available_balances
+            .into_iter()
+            .map(|(token, available)| {
+                let balance = TokenBalance {
+                    available,
+                    ..Default::default()
+                };
+                (token, balance)
+            })
+            .collect();
+        Self { balances }
+    }
+
+    pub fn tokens(&self) -> impl Iterator<Item = &String> {
+        self.balances.keys()
+    }
+
+    pub fn get_balance(&self, token: &str) -> TokenBalance {
+        self.balances.get(token).cloned().unwrap_or_default()
+    }
+
+    pub fn ensure_available(&self, token: &str, amount: u64) -> Result<(), anyhow::Error> {
+        let available = self.get_balance(token).available;
+        ensure!(
+            amount <= available,
+            "The amount {} of '{}' exceeds the available inventory of {}",
+            amount,
+            token,
+            available
+        );
+        Ok(())
+    }
+
+    // called when we lock funds in one of our contracts
+    pub fn reserve(&mut self, token: &str, amount: u64) -> Result<(), anyhow::Error> {
+        self.ensure_available(token, amount)?;
+        let balance = self.get_balance_mut(token)?;
+        balance.available -= amount;
+        balance.reserved += amount;
+        Ok(())
+    }
+
+    // called when we get back the funds of one of our contracts
+    pub fn release(&mut self, token: &str, amount: u64) -> Result<(), anyhow::Error> {
+        let balance = self.get_balance_mut(token)?;
+        ensure!(
+            amount <= balance.reserved,
+            "Cannot release {} of '{}', only {} is reserved",
+            amount,
+            token,
+            balance.reserved
+        );
+        balance.reserved -= amount;
+        balance.available += amount;
+        Ok(())
+    }
+
+    // called when a swap is completed, our reserved funds now belong to the client
+    pub fn settle(
+        &mut self,
+        provided_token: &str,
+        provided_amount: u64,
+        received_token: &str,
+        received_amount: u64,
+    ) -> Result<(), anyhow::Error> {
+        let provided_balance = self.get_balance_mut(provided_token)?;
+        ensure!(
+            provided_amount <= provided_balance.reserved,
+            "Cannot settle {} of '{}', only {} is reserved",
+            provided_amount,
+            provided_token,
+            provided_balance.reserved
+        );
+        provided_balance.reserved -= provided_amount;
+
+        let received_balance = self.balances.entry(received_token.to_string()).or_default();
+        received_balance.settled += received_amount;
+        Ok(())
+    }
+
+    // called when our actual funds are lower than the available balance (e.g. after a restart)
+    pub fn limit_available(&mut self, token: &str, funds: u64) -> Result<(), anyhow::Error> {
+        let balance = self.get_balance_mut(token)?;
+        balance.available = balance.available.min(funds);
+        Ok(())
+    }
+
+    fn get_balance_mut(&mut self, token: &str) -> Result<&mut TokenBalance, anyhow::Error> {
+        self.balances
+            .get_mut(token)
+            .ok_or_else(|| anyhow!("There is no inventory for token '{}'", token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_inventory() -> Inventory {
+        Inventory::new(HashMap::from([("tari".to_string(), 100)]))
+    }
+
+    fn balance(available: u64, reserved: u64, settled: u64) -> TokenBalance {
+        TokenBalance {
+            available,
+            reserved,
+            settled,
+        }
+    }
+
+    #[test]
+    fn it_reserves_the_available_balance() {
+        let mut inventory = new_inventory();
+
+        inventory.reserve("tari", 60).unwrap();
+        assert_eq!(inventory.get_balance("tari"), balance(40, 60, 0));
+        inventory.reserve("tari", 40).unwrap();
+        assert_eq!(inventory.get_balance("tari"), balance(0, 100, 0));
+    }
+
+    #[test]
+    fn it_does_not_reserve_more_than_available() {
+        let mut inventory = new_inventory();
+
+        assert!(inventory.reserve("tari", 101).is_err());
+        assert!(inventory.reserve("eth.wei", 1).is_err());
+        assert_eq!(inventory.get_balance("tari"), balance(100, 0, 0));
+    }
+
+    #[test]
+    fn it_releases_the_reserved_balance() {
+        let mut inventory = new_inventory();
+        inventory.reserve("tari", 60).unwrap();
+
+        inventory.release("tari", 20).unwrap();
+        assert_eq!(inventory.get_balance("tari"), balance(60, 40, 0));
+        assert!(inventory.release("tari", 41).is_err());
+        assert_eq!(inventory.get_balance("tari"), balance(60, 40, 0));
+    }
+
+    #[test]
+    fn it_settles_the_completed_swaps() {
+        let mut inventory = new_inventory();
+        inventory.reserve("tari", 60).unwrap();
+
+        inventory.settle("tari", 60, "eth.wei", 120).unwrap();
+        assert_eq!(inventory.get_balance("tari"), balance(40, 0, 0));
+        assert_eq!(inventory.get_balance("eth.wei"), balance(0, 0, 120));
+
+        // the settled funds are not available for new swaps
+        assert!(inventory.ensure_available("eth.wei", 1).is_err());
+        assert!(inventory.settle("tari", 1, "eth.wei", 1).is_err());
+    }
+
+    #[test]
+    fn it_limits_the_available_balance_to_our_funds() {
+        let mut inventory = new_inventory();
+        inventory.reserve("tari", 60).unwrap();
+
+        inventory.limit_available("tari", 100).unwrap();
+        assert_eq!(inventory.get_balance("tari"), balance(40, 60, 0));
+        inventory.limit_available("tari", 30).unwrap();
+        assert_eq!(inventory.get_balance("tari"), balance(30, 60, 0));
+        assert!(inventory.limit_available("eth.wei", 0).is_err());
+    }
+}
@@ -4,15 +4,45 @@ use axum_jrpc::{
     error::{JsonRpcError, JsonRpcErrorReason},
     JrpcResult, JsonRpcExtractor, JsonRpcResponse,
 };
+use htlc::HtlcError;
 use serde::{Deserialize, Serialize};
 use serde_json::{self as json};
+use serde_with::{serde_as, DisplayFromStr};
 
-use crate::swap_manager::{ContractId, Preimage, Proposal, SwapManager};
+use crate::quote_book::{Quote, QuoteSignature};
+use crate::swap_manager::{ContractId, Preimage, Proposal, SwapId, SwapManager};
+use crate::swap_state::{NextAction, SwapFilter, SwapState, SwapStatus, SwapTransition};
+
+// server error codes (from -32000 to -32099) for the failures of the network transactions
+const NETWORK_ERROR_CODE: i32 = -32000;
+const TRANSACTION_SEND_ERROR_CODE: i32 = -32001;
+const TRANSACTION_REVERTED_ERROR_CODE: i32 = -32002;
+const TRANSACTION_MISSING_RECEIPT_ERROR_CODE: i32 = -32003;
+const TRANSACTION_DROPPED_ERROR_CODE: i32 = -32004;
+const UNAUTHORIZED_ERROR_CODE: i32 = -32005;
+
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetQuoteRequest {
+    pub provided_token: String,
+    #[serde_as(as = "DisplayFromStr")]
+    pub provided_token_balance: u64,
+    pub requested_token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetQuoteResponse {
+    #[serde(flatten)]
+    pub quote: Quote,
+    pub signature: QuoteSignature,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RequestSwapResponse {
     pub swap_id: String,
     pub provider_address: String,
+    // unix timestamp (in seconds) until which the client can call "request_lock_funds"
+    pub lock_deadline: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,13 +62,88 @@ pub struct PushPreimageRequest {
     pub preimage: Preimage,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetSwapStatusRequest {
+    pub swap_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapStatusResponse {
+    pub swap_id: String,
+    pub status: SwapStatus,
+    pub client_contract_id: Option<ContractId>,
+    pub our_contract_id: Option<ContractId>,
+    pub lock_deadline: u64,
+    pub client_timelock: Option<u64>,
+    pub our_timelock: Option<u64>,
+    pub unconfirmed_lock: Option<String>,
+    pub next_action: NextAction,
+    pub transitions: Vec<SwapTransition>,
+}
+
+impl SwapStatusResponse {
+    fn new(swap_id: SwapId, swap_state: SwapState) -> Self {
+        Self {
+            swap_id: swap_id.to_string(),
+            status: swap_state.status(),
+            next_action: swap_state.next_action(),
+            client_contract_id: swap_state.client_contract_id,
+            our_contract_id: swap_state.our_contract_id,
+            lock_deadline: swap_state.lock_deadline,
+            client_timelock: swap_state.client_timelock,
+            our_timelock: swap_state.our_timelock,
+            unconfirmed_lock: swap_state.unconfirmed_lock,
+            transitions: swap_state.transitions,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListSwapsRequest {
+    pub auth_token: String,
+    #[serde(flatten)]
+    pub filter: SwapFilter,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListSwapsResponse {
+    pub swaps: Vec<SwapStatusResponse>,
+}
+
 pub struct JsonRpcHandlers {
     swap_manager: Arc<SwapManager>,
+    // the operator methods are disabled if not specified
+    admin_token: Option<String>,
 }
 
 impl JsonRpcHandlers {
-    pub fn new(swap_manager: Arc<SwapManager>) -> Self {
-        Self { swap_manager }
+    pub fn new(swap_manager: Arc<SwapManager>, admin_token: Option<String>) -> Self {
+        Self {
+            swap_manager,
+            admin_token,
+        }
+    }
+
+    pub async fn get_quote(&self, value: JsonRpcExtractor) -> JrpcResult {
+        let answer_id = value.get_answer_id();
+        let request: GetQuoteRequest = value.parse_params()?;
+
+        let result = self
+            .swap_manager
+            .get_quote(
+                request.provided_token,
+                request.provided_token_balance,
+                request.requested_token,
+            )
+            .await;
+
+        match result {
+            Ok((quote, signature)) => {
+                let response = GetQuoteResponse { quote, signature };
+                Ok(JsonRpcResponse::success(answer_id, response))
+            }
+            Err(e) => jrpc_error(answer_id, "Quote request rejected", e),
+        }
     }
 
     pub async fn request_swap(&self, value: JsonRpcExtractor) -> JrpcResult {
@@ -48,14 +153,15 @@ impl JsonRpcHandlers {
         let result = self.swap_manager.request_swap(proposal).await;
 
         match result {
-            Ok((swap_id, provider_address)) => {
+            Ok((swap_id, provider_address, lock_deadline)) => {
                 let response = RequestSwapResponse {
                     swap_id: swap_id.to_string(),
                     provider_address,
+                    lock_deadline,
                 };
                 Ok(JsonRpcResponse::success(answer_id, response))
             }
-            Err(e) => jrpc_error(answer_id, format!("Swap request rejected: {}", e)),
+            Err(e) => jrpc_error(answer_id, "Swap request rejected", e),
         }
     }
 
@@ -73,7 +179,7 @@ impl JsonRpcHandlers {
                 let response = LockFundsResponse { contract_id };
                 Ok(JsonRpcResponse::success(answer_id, response))
             }
-            Err(e) => jrpc_error(answer_id, format!("Lock funds request rejected: {}", e)),
+            Err(e) => jrpc_error(answer_id, "Lock funds request rejected", e),
         }
     }
 
@@ -88,18 +194,97 @@ impl JsonRpcHandlers {
 
         match result {
             Ok(_) => Ok(JsonRpcResponse::success(answer_id, ())),
-            Err(e) => jrpc_error(answer_id, format!("Lock funds request rejected: {}", e)),
+            Err(e) => jrpc_error(answer_id, "Push preimage request rejected", e),
+        }
+    }
+
+    pub async fn get_swap_status(&self, value: JsonRpcExtractor) -> JrpcResult {
+        let answer_id = value.get_answer_id();
+        let request: GetSwapStatusRequest = value.parse_params()?;
+
+        let result = self.swap_manager.get_swap(request.swap_id).await;
+
+        match result {
+            Ok((swap_id, swap_state)) => {
+                let response = SwapStatusResponse::new(swap_id, swap_state);
+                Ok(JsonRpcResponse::success(answer_id, response))
+            }
+            Err(e) => jrpc_error(answer_id, "Get swap status request rejected", e),
+        }
+    }
+
+    // only for operators, as it exposes the swaps of all the clients
+    pub async fn list_swaps(&self, value: JsonRpcExtractor) -> JrpcResult {
+        let answer_id = value.get_answer_id();
+        let request: ListSwapsRequest = value.parse_params()?;
+
+        if !self.is_admin(&request.auth_token) {
+            return Err(JsonRpcResponse::error(
+                answer_id,
+                JsonRpcError::new(
+                    JsonRpcErrorReason::ServerError(UNAUTHORIZED_ERROR_CODE),
+                    "List swaps request rejected: invalid auth token".to_string(),
+                    json::Value::Null,
+                ),
+            ));
+        }
+
+        let result = self.swap_manager.list_swaps(&request.filter).await;
+
+        match result {
+            Ok(swaps) => {
+                let response = ListSwapsResponse {
+                    swaps: swaps
+                        .into_iter()
+                        .map(|(swap_id, swap_state)| SwapStatusResponse::new(swap_id, swap_state))
+                        .collect(),
+                };
+                Ok(JsonRpcResponse::success(answer_id, response))
+            }
+            Err(e) => jrpc_error(answer_id, "List swaps request rejected", e),
+        }
+    }
+
+    fn is_admin(&self, auth_token: &str) -> bool {
+        match &self.admin_token {
+            Some(admin_token) => constant_time_eq(admin_token.as_bytes(), auth_token.as_bytes()),
+            None => false,
         }
     }
 }
 
-fn jrpc_error(answer_id: i64, message: String) -> JrpcResult {
+// so the response time does not leak how much of the token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn jrpc_error(answer_id: i64, context: &str, error: anyhow::Error) -> JrpcResult {
     Err(JsonRpcResponse::error(
         answer_id,
         JsonRpcError::new(
-            JsonRpcErrorReason::InternalError,
-            message,
+            error_reason(&error),
+            format!("{}: {}", context, error),
             json::Value::Null,
         ),
     ))
 }
+
+// the network errors have their own codes, so clients can tell them apart from rejected requests
+fn error_reason(error: &anyhow::Error) -> JsonRpcErrorReason {
+    match error.downcast_ref::<HtlcError>() {
+        Some(HtlcError::NetworkError { .. }) => JsonRpcErrorReason::ServerError(NETWORK_ERROR_CODE),
+        Some(HtlcError::SendError { .. }) => {
+            JsonRpcErrorReason::ServerError(TRANSACTION_SEND_ERROR_CODE)
+        }
+        Some(HtlcError::TransactionReverted { .. }) => {
+            JsonRpcErrorReason::ServerError(TRANSACTION_REVERTED_ERROR_CODE)
+        }
+        Some(HtlcError::MissingReceipt { .. }) => {
+            JsonRpcErrorReason::ServerError(TRANSACTION_MISSING_RECEIPT_ERROR_CODE)
+        }
+        Some(HtlcError::TransactionDropped { .. }) => {
+            JsonRpcErrorReason::ServerError(TRANSACTION_DROPPED_ERROR_CODE)
+        }
+        _ => JsonRpcErrorReason::InternalError,
+    }
+}
@@ -32,12 +32,15 @@ async fn handler(
     Extension(handlers): Extension<Arc<JsonRpcHandlers>>,
     value: JsonRpcExtractor,
 ) -> JrpcResult {
+    // the bodies are not logged, as they carry secrets like the admin token of "list_swaps"
     debug!(target: LOG_TARGET, "🌐 JSON-RPC request: {}", value.method);
-    debug!(target: LOG_TARGET, "🌐 JSON-RPC body: {:?}", value);
     match value.method.as_str() {
+        "get_quote" => handlers.get_quote(value).await,
         "request_swap" => handlers.request_swap(value).await,
         "request_lock_funds" => handlers.request_lock_funds(value).await,
         "push_preimage" => handlers.push_preimage(value).await,
+        "get_swap_status" => handlers.get_swap_status(value).await,
+        "list_swaps" => handlers.list_swaps(value).await,
         method => Ok(value.method_not_found(method)),
     }
 }
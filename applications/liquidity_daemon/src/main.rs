@@ -1,27 +1,50 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{collections::HashMap, net::SocketAddr, str::FromStr, sync::Arc, time::Duration};
 
 use crate::{
     cli::Cli,
     config::Config,
     json_rpc::{run_json_rpc, JsonRpcHandlers},
 };
-use ethereum::EthereumContractManager;
-use ethers::signers::LocalWallet;
-use log::info;
+use ethereum::{
+    new_client, EthereumContractManager, EthereumErc20ContractManager,
+    EthereumErc721ContractManager,
+};
+use ethers::{signers::LocalWallet, types::U256};
+use log::{info, warn};
 use position_manager::PositionManager;
-use swap_manager::SwapManager;
-use tari::contract::TariContractManager;
-use tari_crypto::{ristretto::RistrettoPublicKey, tari_utilities::hex::Hex};
-use tari_template_lib::prelude::TemplateAddress;
-use tokio::{signal, task};
+use quote_book::QuoteBook;
+use swap_checks::{SwapCheck, SwapCheckRunner};
+use swap_manager::{HtlcBackends, SwapManager};
+use swap_store::{MemorySwapStore, SledSwapStore, SwapStore};
+use tari::{contract::TariContractManager, TariHtlcBackend, TariResource};
+use tari_crypto::{
+    keys::PublicKey,
+    ristretto::{RistrettoPublicKey, RistrettoSecretKey},
+    tari_utilities::hex::Hex,
+};
+use tari_template_lib::prelude::{
+    NonFungibleId, ResourceAddress, TemplateAddress, CONFIDENTIAL_TARI_RESOURCE_ADDRESS,
+};
+use timelock_policy::TimelockPolicy;
+use token::{AssetKind, ETH_WEI_TOKEN, TARI_TOKEN};
+use tokio::{signal, sync::RwLock, task};
 
 mod cli;
 mod config;
+mod inventory;
 mod json_rpc;
 mod position_manager;
+mod quote_book;
+mod swap_checks;
 mod swap_manager;
+mod swap_state;
+mod swap_store;
+mod timelock_policy;
+mod token;
 
 const LOG_TARGET: &str = "liquidity_daemon";
+const SWAP_SUPERVISOR_INTERVAL: Duration = Duration::from_secs(60);
+const CHAIN_WATCHER_INTERVAL: Duration = Duration::from_secs(10);
 
 #[tokio::main]
 async fn main() {
@@ -45,13 +68,13 @@ async fn main() {
     // TODO: properly handle private keys
     info!("Initializing Ethereum manager...");
     let eth_wallet = config.ethereum.private_key.parse::<LocalWallet>().unwrap();
-    let eth_manager = EthereumContractManager::new(
-        eth_wallet,
-        config.ethereum.rpc_url.clone(),
-        config.ethereum.contract_address.clone(),
-    )
-    .await
-    .expect("Could not initialize the Ethereum manager");
+    // shared by all the Ethereum managers, so their concurrent transactions never use the same nonce
+    let eth_client = new_client(eth_wallet, config.ethereum.rpc_url.clone())
+        .await
+        .expect("Could not initialize the Ethereum client");
+    let eth_manager =
+        EthereumContractManager::new(eth_client.clone(), config.ethereum.contract_address.clone())
+            .expect("Could not initialize the Ethereum manager");
 
     // init the tari manager
     info!("Initializing Tari manager...");
@@ -59,27 +82,175 @@ async fn main() {
         RistrettoPublicKey::from_hex(&config.tari.public_key).expect("Invalid Tari public key ");
     let tari_swap_template = TemplateAddress::from_hex(&config.tari.swap_template)
         .expect("Invalid Tari swap template address ");
-    let tari_manager = TariContractManager::new(
-        config.tari.wallet_endpoint.clone(),
-        tari_public_key,
-        config.tari.public_key_index,
-        config.tari.wallet_token.clone(),
-        tari_swap_template,
-    )
-    .expect("Could not initialize the Tari manager");
+    let new_tari_manager = || {
+        TariContractManager::new(
+            config.tari.wallet_endpoint.clone(),
+            tari_public_key.clone(),
+            config.tari.public_key_index,
+            config.tari.wallet_token.clone(),
+            tari_swap_template,
+        )
+        .expect("Could not initialize the Tari manager")
+    };
+    let tari_backend = TariHtlcBackend::new(
+        new_tari_manager(),
+        TariResource::Fungible(*CONFIDENTIAL_TARI_RESOURCE_ADDRESS),
+    );
+
+    // the swap manager only knows about the tokens through their HTLC backends
+    let token_registry = config
+        .token_registry()
+        .expect("Could not initialize the token registry");
+    let mut backends: HtlcBackends = HashMap::new();
+    backends.insert(
+        ETH_WEI_TOKEN.to_string(),
+        RwLock::new(Box::new(eth_manager)),
+    );
+    backends.insert(TARI_TOKEN.to_string(), RwLock::new(Box::new(tari_backend)));
+
+    // each token and NFT has its own backend over the shared HTLC contracts and swap template
+    for token in token_registry.list() {
+        match &token.kind {
+            AssetKind::Erc20 { contract_address } => {
+                info!("Initializing ERC20 manager for token '{}'...", token.id);
+                let erc20_htlc_address = config
+                    .ethereum
+                    .erc20_contract_address
+                    .clone()
+                    .expect("The ERC20 HTLC contract address is required to swap ERC20 tokens");
+                let erc20_manager = EthereumErc20ContractManager::new(
+                    eth_client.clone(),
+                    erc20_htlc_address,
+                    contract_address.clone(),
+                )
+                .expect("Could not initialize the ERC20 manager");
+                backends.insert(token.id.clone(), RwLock::new(Box::new(erc20_manager)));
+            }
+            AssetKind::Erc721 {
+                contract_address,
+                token_id,
+            } => {
+                info!("Initializing ERC721 manager for token '{}'...", token.id);
+                let erc721_htlc_address = config
+                    .ethereum
+                    .erc721_contract_address
+                    .clone()
+                    .expect("The ERC721 HTLC contract address is required to swap NFTs");
+                let token_id = U256::from_dec_str(token_id).expect("Invalid NFT token id");
+                let erc721_manager = EthereumErc721ContractManager::new(
+                    eth_client.clone(),
+                    erc721_htlc_address,
+                    contract_address.clone(),
+                    token_id,
+                )
+                .expect("Could not initialize the ERC721 manager");
+                backends.insert(token.id.clone(), RwLock::new(Box::new(erc721_manager)));
+            }
+            AssetKind::TariResource { resource_address } => {
+                info!("Initializing Tari manager for resource '{}'...", token.id);
+                let resource_address = ResourceAddress::from_str(resource_address)
+                    .expect("Invalid Tari resource address");
+                let tari_backend = TariHtlcBackend::new(
+                    new_tari_manager(),
+                    TariResource::Fungible(resource_address),
+                );
+                backends.insert(token.id.clone(), RwLock::new(Box::new(tari_backend)));
+            }
+            AssetKind::TariNonFungible {
+                resource_address,
+                token_id,
+            } => {
+                info!("Initializing Tari manager for NFT '{}'...", token.id);
+                let resource_address = ResourceAddress::from_str(resource_address)
+                    .expect("Invalid Tari resource address");
+                let token_id = NonFungibleId::try_from_canonical_string(token_id)
+                    .expect("Invalid Tari NFT id");
+                let tari_backend = TariHtlcBackend::new(
+                    new_tari_manager(),
+                    TariResource::NonFungible(resource_address, token_id),
+                );
+                backends.insert(token.id.clone(), RwLock::new(Box::new(tari_backend)));
+            }
+            AssetKind::TariConfidential {
+                resource_address,
+                view_key_id,
+            } => {
+                info!(
+                    "Initializing Tari manager for confidential resource '{}'...",
+                    token.id
+                );
+                if view_key_id.is_none() {
+                    warn!(
+                        "No view key for '{}', the clients will not be able to pay with it",
+                        token.id
+                    );
+                }
+                let resource_address = ResourceAddress::from_str(resource_address)
+                    .expect("Invalid Tari resource address");
+                let tari_backend = TariHtlcBackend::new(
+                    new_tari_manager(),
+                    TariResource::Confidential(resource_address, *view_key_id),
+                );
+                backends.insert(token.id.clone(), RwLock::new(Box::new(tari_backend)));
+            }
+            AssetKind::Native => {}
+        }
+    }
+
+    // init the swap store
+    let swap_store: Box<dyn SwapStore> = match &config.database_path {
+        Some(path) => {
+            info!("Opening the swap database at '{}'...", path);
+            Box::new(SledSwapStore::open(path).expect("Could not open the swap database"))
+        }
+        None => {
+            warn!("No database path specified, ongoing swaps will be lost on restarts");
+            Box::new(MemorySwapStore::new())
+        }
+    };
+
+    // the clients verify the quotes with the public key that we publish in the Tari network
+    let quote_book = match &config.tari.private_key {
+        Some(private_key) => {
+            let tari_private_key =
+                RistrettoSecretKey::from_hex(private_key).expect("Invalid Tari private key ");
+            assert_eq!(
+                RistrettoPublicKey::from_secret_key(&tari_private_key),
+                tari_public_key,
+                "The Tari private key does not match the public key"
+            );
+            Some(QuoteBook::new(
+                tari_private_key,
+                Duration::from_secs(config.quote_ttl_secs),
+                config.max_quotes,
+            ))
+        }
+        None => {
+            warn!("No Tari private key specified, quotes are disabled");
+            None
+        }
+    };
 
     // init the swap manager
     info!("Initializing the swap manager...");
     let swap_manager = Arc::new(SwapManager::new(
-        config.clone(),
+        token_registry,
         position_manager,
-        eth_manager,
-        tari_manager,
+        backends,
+        swap_store,
+        TimelockPolicy::new(config.timelocks.clone()),
+        quote_book,
+        config.proposals.clone(),
     ));
+    let resumed_swaps = swap_manager
+        .resume_swaps()
+        .await
+        .expect("Could not resume the ongoing swaps");
+    info!("Resumed {} ongoing swaps", resumed_swaps);
 
     // run the swap JSON-RPC
     info!(target: LOG_TARGET, "🌐 Starting JSON-RPC server on {}", config.network_address);
-    let handlers = JsonRpcHandlers::new(swap_manager);
+    let handlers = JsonRpcHandlers::new(swap_manager.clone(), config.admin_token.clone());
     let json_rpc_address: SocketAddr = config
         .network_address
         .trim_start_matches("http://")
@@ -88,7 +259,30 @@ async fn main() {
         .expect("Invalid network address");
     task::spawn(run_json_rpc(json_rpc_address, handlers));
 
-    // TODO: we need a recurring process to keep track of ongoing swaps, and do refunds if they expire
+    // run the recurring process that completes the swaps as soon as the preimage is revealed
+    info!(target: LOG_TARGET, "Starting the chain watcher");
+    let chain_watcher = SwapCheckRunner::new(
+        "chain watcher",
+        swap_manager.clone(),
+        CHAIN_WATCHER_INTERVAL,
+        vec![SwapCheck::CompleteRevealedSwaps],
+    );
+    task::spawn(chain_watcher.run());
+
+    // run the recurring process that refunds our funds in expired swaps
+    info!(target: LOG_TARGET, "Starting the swap supervisor");
+    let swap_supervisor = SwapCheckRunner::new(
+        "swap supervisor",
+        swap_manager,
+        SWAP_SUPERVISOR_INTERVAL,
+        vec![
+            SwapCheck::RefundExpiredSwaps,
+            SwapCheck::ReconcileUnconfirmedLocks,
+            SwapCheck::ExpireAbandonedProposals,
+            SwapCheck::RemoveExpiredProposals,
+        ],
+    );
+    task::spawn(swap_supervisor.run());
 
     match signal::ctrl_c().await {
         Ok(()) => {
@@ -1,17 +1,52 @@
+use std::collections::HashMap;
 use std::str::FromStr;
 
-use log::info;
+use anyhow::{anyhow, ensure};
+use ethers::types::U256;
+use log::{error, info};
 use tari::liquidity::{Position, TariLiquidityManager};
 use tari_crypto::{ristretto::RistrettoPublicKey, tari_utilities::hex::Hex};
 use tari_template_lib::prelude::ComponentAddress;
+use tokio::sync::RwLock;
 
-use crate::config::Config;
+use crate::config::{Config, PositionConfig, MAX_SPREAD_BPS};
+use crate::inventory::Inventory;
 
 const LOG_TARGET: &str = "liquidity_daemon::position_manager";
 
+// A configured position along with the amount of provided token that is still available for swaps
+#[derive(Debug, Clone)]
+struct PositionState {
+    config: PositionConfig,
+    remaining: u64,
+}
+
+impl PositionState {
+    // the position with the balance that we can actually provide, at the price that "validate_price" accepts
+    fn to_position(&self, inventory: &Inventory) -> Position {
+        let position = &self.config.position;
+        let available = inventory.get_balance(&position.provided_token).available;
+        let provided_token_balance = self.remaining.min(available);
+
+        // the spread is applied over the configured price, rounding up in our favor
+        let divisor = provided_value(&self.config, 1);
+        let requested_token_balance =
+            (requested_value(&self.config, provided_token_balance) + divisor - 1) / divisor;
+
+        Position {
+            provided_token: position.provided_token.clone(),
+            provided_token_balance,
+            requested_token: position.requested_token.clone(),
+            requested_token_balance: requested_token_balance.min(U256::from(u64::MAX)).as_u64(),
+        }
+    }
+}
+
 pub struct PositionManager {
     config: Config,
-    tari_manager: TariLiquidityManager,
+    tari_manager: RwLock<TariLiquidityManager>,
+    positions: RwLock<Vec<PositionState>>,
+    inventory: RwLock<Inventory>,
 }
 
 impl PositionManager {
@@ -29,61 +64,420 @@ impl PositionManager {
         )
         .await?;
 
+        let positions = config
+            .positions
+            .iter()
+            .map(|p| PositionState {
+                config: p.clone(),
+                remaining: p.position.provided_token_balance,
+            })
+            .collect();
+
+        // all the positions that provide the same token share its inventory
+        let mut available_balances = HashMap::new();
+        for p in &config.positions {
+            *available_balances
+                .entry(p.position.provided_token.clone())
+                .or_insert(0) += p.position.provided_token_balance;
+        }
+        let inventory = Inventory::new(available_balances);
+
         Ok(Self {
             config,
-            tari_manager,
+            tari_manager: RwLock::new(tari_manager),
+            positions: RwLock::new(positions),
+            inventory: RwLock::new(inventory),
         })
     }
 
     pub async fn sync(&mut self) -> Result<(), anyhow::Error> {
         let config_network_address = self.config.network_address.clone();
-        let config_positions = self.config.positions.clone();
-        if self.is_registered() {
+        let config_positions = self.get_positions().await;
+        let tari_manager = self.tari_manager.get_mut();
+        if tari_manager.lp_position_component.is_some() {
             // we are registered
             // we need to update the network address if it changed in the config
-            let published_network_address = self.tari_manager.get_network_address().await?;
+            let published_network_address = tari_manager.get_network_address().await?;
             info!(target: LOG_TARGET, "published network address {:?}", published_network_address);
             if published_network_address != config_network_address {
-                self.tari_manager
+                tari_manager
                     .set_network_address(config_network_address)
                     .await?;
             }
 
             // we also need to update the positions if they changed in the config
-            let published_positions = self.tari_manager.get_positions().await?;
+            let published_positions = tari_manager.get_positions().await?;
             info!(target: LOG_TARGET, "published positions {:?}", published_positions);
             if published_positions != config_positions {
-                self.tari_manager
-                    .replace_positions(config_positions)
-                    .await?;
+                tari_manager.replace_positions(config_positions).await?;
             }
         } else {
             // we are not registered
             // if the user has specified some positions, we need to register and publish them
             if !self.config.positions.is_empty() {
-                self.tari_manager.register(config_network_address).await?;
-                self.tari_manager
-                    .replace_positions(config_positions)
-                    .await?;
+                tari_manager.register(config_network_address).await?;
+                tari_manager.replace_positions(config_positions).await?;
             }
         }
 
         Ok(())
     }
 
-    fn is_registered(&mut self) -> bool {
-        self.tari_manager.lp_position_component.is_some()
+    pub async fn get_positions(&self) -> Vec<Position> {
+        let positions = self.positions.read().await;
+        let inventory = self.inventory.read().await;
+        positions
+            .iter()
+            .map(|p| p.to_position(&inventory))
+            .collect()
+    }
+
+    // checks that the proposal (from the client point of view) is covered by one of our positions
+    pub async fn validate_swap_proposal(&self, proposal: &Position) -> Result<(), anyhow::Error> {
+        let positions = self.positions.read().await;
+        let index = find_matching_position(&positions, proposal)?;
+        validate_fill(&positions[index], proposal)?;
+        validate_price(&positions[index].config, proposal)?;
+
+        let inventory = self.inventory.read().await;
+        inventory.ensure_available(&proposal.requested_token, proposal.requested_token_balance)
+    }
+
+    // the proposal (from the client point of view) that gets the most of our position for the provided amount
+    pub async fn quote(
+        &self,
+        provided_token: String,
+        provided_token_balance: u64,
+        requested_token: String,
+    ) -> Result<Position, anyhow::Error> {
+        let mut proposal = Position {
+            provided_token,
+            provided_token_balance,
+            requested_token,
+            requested_token_balance: 0,
+        };
+        let positions = self.positions.read().await;
+        let index = find_matching_position(&positions, &proposal)?;
+        proposal.requested_token_balance =
+            max_fill(&positions[index].config, provided_token_balance)?;
+        validate_fill(&positions[index], &proposal)?;
+
+        let inventory = self.inventory.read().await;
+        inventory.ensure_available(&proposal.requested_token, proposal.requested_token_balance)?;
+        Ok(proposal)
+    }
+
+    // reserves the balance of the matching position when we lock funds for a swap,
+    // the price is not checked again as it was agreed when the proposal was accepted
+    pub async fn fill(&self, proposal: &Position) -> Result<(), anyhow::Error> {
+        {
+            let mut positions = self.positions.write().await;
+            let mut inventory = self.inventory.write().await;
+            let index = find_matching_position(&positions, proposal)?;
+            validate_fill(&positions[index], proposal)?;
+            inventory.reserve(&proposal.requested_token, proposal.requested_token_balance)?;
+
+            positions[index].remaining -= proposal.requested_token_balance;
+            info!(
+                target: LOG_TARGET,
+                "Position {:?} filled with {}, remaining {}",
+                positions[index].config.position,
+                proposal.requested_token_balance,
+                positions[index].remaining
+            );
+        }
+
+        self.publish_positions().await;
+        Ok(())
+    }
+
+    // gives back the balance to the matching position when we refund our funds in a swap
+    pub async fn cancel_fill(&self, proposal: &Position) -> Result<(), anyhow::Error> {
+        {
+            let mut positions = self.positions.write().await;
+            let mut inventory = self.inventory.write().await;
+            let index = find_matching_position(&positions, proposal)?;
+            inventory.release(&proposal.requested_token, proposal.requested_token_balance)?;
+
+            positions[index].remaining += proposal.requested_token_balance;
+            info!(
+                target: LOG_TARGET,
+                "Position {:?} fill of {} cancelled, remaining {}",
+                positions[index].config.position,
+                proposal.requested_token_balance,
+                positions[index].remaining
+            );
+        }
+
+        self.publish_positions().await;
+        Ok(())
     }
 
-    pub fn get_positions(&self) -> Vec<Position> {
-        self.config.positions.clone()
+    pub async fn provided_tokens(&self) -> Vec<String> {
+        let inventory = self.inventory.read().await;
+        inventory.tokens().cloned().collect()
     }
 
-    pub async fn is_swap_proposal_valid(&self, proposal: &Position) -> bool {
-        // TODO: check ratio to know if the provided token amount by the client is correct
-        self.get_positions().iter().any(|p| {
-            p.provided_token == proposal.requested_token
-                && p.requested_token == proposal.provided_token
+    // the inventory starts from the configured balances, which can be higher than our actual funds
+    pub async fn limit_available(&self, token: &str, funds: u64) -> Result<(), anyhow::Error> {
+        {
+            let mut inventory = self.inventory.write().await;
+            inventory.limit_available(token, funds)?;
+            info!(
+                target: LOG_TARGET,
+                "Inventory of '{}' synced with our funds of {}: {:?}",
+                token,
+                funds,
+                inventory.get_balance(token)
+            );
+        }
+
+        self.publish_positions().await;
+        Ok(())
+    }
+
+    // accounts the funds of a completed swap, the published positions are not affected
+    pub async fn settle_fill(&self, proposal: &Position) -> Result<(), anyhow::Error> {
+        let mut inventory = self.inventory.write().await;
+        inventory.settle(
+            &proposal.requested_token,
+            proposal.requested_token_balance,
+            &proposal.provided_token,
+            proposal.provided_token_balance,
+        )
+    }
+
+    // the positions are only replaced in the network if they differ from the published ones
+    async fn publish_positions(&self) {
+        let positions = self.get_positions().await;
+        let mut tari_manager = self.tari_manager.write().await;
+
+        let result = match tari_manager.get_positions().await {
+            Ok(published_positions) if published_positions == positions => return,
+            Ok(_) => tari_manager.replace_positions(positions.clone()).await,
+            Err(e) => Err(e),
+        };
+
+        // the balances will be published again in the next change, so we don't fail the swap
+        match result {
+            Ok(()) => info!(target: LOG_TARGET, "published positions {:?}", positions),
+            Err(e) => error!(target: LOG_TARGET, "Could not publish positions: {}", e),
+        }
+    }
+}
+
+// we assume that there is at most one position for each token pair
+fn find_matching_position(
+    positions: &[PositionState],
+    proposal: &Position,
+) -> Result<usize, anyhow::Error> {
+    positions
+        .iter()
+        .position(|p| {
+            p.config.position.provided_token == proposal.requested_token
+                && p.config.position.requested_token == proposal.provided_token
         })
+        .ok_or_else(|| anyhow!("There is no position for the requested token pair"))
+}
+
+// fill / provided <= (position provided / position requested) * (1 - spread)
+// is cross multiplied into "requested_value(fill) <= provided_value(provided)" to avoid rounding errors,
+// the products of three u64 values can overflow an u128 but always fit in an U256
+fn requested_value(config: &PositionConfig, fill: u64) -> U256 {
+    U256::from(fill)
+        * U256::from(config.position.requested_token_balance)
+        * U256::from(MAX_SPREAD_BPS)
+}
+
+fn provided_value(config: &PositionConfig, provided_token_balance: u64) -> U256 {
+    U256::from(provided_token_balance)
+        * U256::from(config.position.provided_token_balance)
+        * U256::from(MAX_SPREAD_BPS - config.spread_bps)
+}
+
+// the highest fill accepted by "validate_price" for the provided amount, rounding down in our favor
+fn max_fill(config: &PositionConfig, provided_token_balance: u64) -> Result<u64, anyhow::Error> {
+    let fill = provided_value(config, provided_token_balance) / requested_value(config, 1);
+    u64::try_from(fill)
+        .map_err(|_| anyhow!("The provided amount {} is too high", provided_token_balance))
+}
+
+fn validate_fill(position: &PositionState, proposal: &Position) -> Result<(), anyhow::Error> {
+    // the amount that we would provide in the swap
+    let fill = proposal.requested_token_balance;
+    ensure!(fill > 0, "The requested amount cannot be zero");
+
+    if let Some(min_fill) = position.config.min_fill {
+        ensure!(
+            fill >= min_fill,
+            "The requested amount {} is below the minimum of {}",
+            fill,
+            min_fill
+        );
+    }
+    if let Some(max_fill) = position.config.max_fill {
+        ensure!(
+            fill <= max_fill,
+            "The requested amount {} is above the maximum of {}",
+            fill,
+            max_fill
+        );
+    }
+    ensure!(
+        fill <= position.remaining,
+        "The requested amount {} exceeds the remaining balance of {}",
+        fill,
+        position.remaining
+    );
+
+    Ok(())
+}
+
+fn validate_price(config: &PositionConfig, proposal: &Position) -> Result<(), anyhow::Error> {
+    ensure!(
+        requested_value(config, proposal.requested_token_balance)
+            <= provided_value(config, proposal.provided_token_balance),
+        "The requested amount {} is too high for the provided amount {}",
+        proposal.requested_token_balance,
+        proposal.provided_token_balance
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1 provided token for 2 requested tokens, with a 1% spread
+    fn position_config() -> PositionConfig {
+        PositionConfig {
+            position: Position {
+                provided_token: "tari".to_string(),
+                provided_token_balance: 1_000,
+                requested_token: "eth.wei".to_string(),
+                requested_token_balance: 2_000,
+            },
+            min_fill: Some(10),
+            max_fill: Some(500),
+            spread_bps: 100,
+        }
+    }
+
+    fn position_state(config: PositionConfig) -> PositionState {
+        PositionState {
+            remaining: config.position.provided_token_balance,
+            config,
+        }
+    }
+
+    // from the client point of view
+    fn proposal(provided_token_balance: u64, requested_token_balance: u64) -> Position {
+        Position {
+            provided_token: "eth.wei".to_string(),
+            provided_token_balance,
+            requested_token: "tari".to_string(),
+            requested_token_balance,
+        }
+    }
+
+    #[test]
+    fn it_accepts_fills_up_to_the_spread() {
+        let config = position_config();
+
+        // 200 * 1/2 * 0.99 = 99
+        validate_price(&config, &proposal(200, 99)).unwrap();
+        validate_price(&config, &proposal(200, 50)).unwrap();
+        let err = validate_price(&config, &proposal(200, 100)).unwrap_err();
+        assert!(err.to_string().contains("too high"));
+    }
+
+    #[test]
+    fn it_accepts_the_whole_price_without_spread() {
+        let mut config = position_config();
+        config.spread_bps = 0;
+
+        validate_price(&config, &proposal(200, 100)).unwrap();
+        assert!(validate_price(&config, &proposal(200, 101)).is_err());
+    }
+
+    #[test]
+    fn it_enforces_the_fill_limits() {
+        let mut position = position_state(position_config());
+
+        assert!(validate_fill(&position, &proposal(0, 0)).is_err());
+        assert!(validate_fill(&position, &proposal(21, 9)).is_err());
+        validate_fill(&position, &proposal(21, 10)).unwrap();
+        validate_fill(&position, &proposal(2_000, 500)).unwrap();
+        assert!(validate_fill(&position, &proposal(2_000, 501)).is_err());
+
+        position.remaining = 400;
+        let err = validate_fill(&position, &proposal(2_000, 401)).unwrap_err();
+        assert!(err.to_string().contains("remaining"));
+    }
+
+    #[test]
+    fn it_rounds_the_max_fill_down() {
+        let config = position_config();
+
+        // 201 * 1/2 * 0.99 = 99.495
+        let fill = max_fill(&config, 201).unwrap();
+        assert_eq!(fill, 99);
+        validate_price(&config, &proposal(201, fill)).unwrap();
+        assert!(validate_price(&config, &proposal(201, fill + 1)).is_err());
+
+        assert_eq!(max_fill(&config, 1).unwrap(), 0);
+    }
+
+    #[test]
+    fn it_handles_huge_balances() {
+        // two tokens with 18 decimals, whose products overflow an u128
+        let config = PositionConfig {
+            position: Position {
+                provided_token: "tari".to_string(),
+                provided_token_balance: u64::MAX,
+                requested_token: "eth.wei".to_string(),
+                requested_token_balance: u64::MAX - 1,
+            },
+            min_fill: None,
+            max_fill: None,
+            spread_bps: 1,
+        };
+
+        let fill = max_fill(&config, u64::MAX - 1).unwrap();
+        assert!(fill < u64::MAX - 1);
+        validate_price(&config, &proposal(u64::MAX - 1, fill)).unwrap();
+        assert!(validate_price(&config, &proposal(u64::MAX - 1, fill + 1)).is_err());
+
+        // more than an u64 can hold
+        let mut config = config;
+        config.position.requested_token_balance = 1;
+        config.spread_bps = 0;
+        assert!(max_fill(&config, 2).is_err());
+    }
+
+    #[test]
+    fn it_publishes_the_price_with_the_spread() {
+        let config = position_config();
+        let mut position = position_state(config.clone());
+        let inventory = Inventory::new(HashMap::from([("tari".to_string(), 500)]));
+
+        // limited by the inventory
+        let published = position.to_position(&inventory);
+        assert_eq!(published.provided_token_balance, 500);
+        // 500 * 2 / 0.99 = 1010.1
+        assert_eq!(published.requested_token_balance, 1011);
+
+        // a client following the published price is accepted
+        position.config.max_fill = None;
+        let client_proposal = proposal(
+            published.requested_token_balance,
+            published.provided_token_balance,
+        );
+        validate_fill(&position, &client_proposal).unwrap();
+        validate_price(&config, &client_proposal).unwrap();
+        assert_eq!(
+            max_fill(&config, published.requested_token_balance).unwrap(),
+            published.provided_token_balance
+        );
     }
 }
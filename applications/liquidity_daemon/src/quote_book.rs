@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, ensure};
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use tari_crypto::{
+    ristretto::{RistrettoSchnorr, RistrettoSecretKey},
+    tari_utilities::hex::Hex,
+};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::swap_manager::Position;
+use crate::swap_state::unix_timestamp;
+
+pub type QuoteId = Uuid;
+
+// A price that we commit to honor in a single swap until it expires
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quote {
+    pub quote_id: QuoteId,
+    // from the client point of view, like the proposals
+    pub position: Position,
+    // unix timestamp (in seconds) from which the quote cannot be used
+    pub expires_at: u64,
+}
+
+impl Quote {
+    // the signed message, so clients can verify the quote against our published Tari public key
+    pub fn message(&self) -> String {
+        format!(
+            "liquidity_daemon.quote:{}:{}:{}:{}:{}:{}",
+            self.quote_id,
+            self.position.provided_token,
+            self.position.provided_token_balance,
+            self.position.requested_token,
+            self.position.requested_token_balance,
+            self.expires_at
+        )
+    }
+}
+
+// Schnorr signature over the Ristretto curve, with both parts hex encoded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuoteSignature {
+    pub public_nonce: String,
+    pub signature: String,
+}
+
+// The quotes are only kept in memory, so they are all invalidated on restarts
+pub struct QuoteBook {
+    secret_key: RistrettoSecretKey,
+    ttl: Duration,
+    max_quotes: usize,
+    quotes: RwLock<HashMap<QuoteId, Quote>>,
+}
+
+impl QuoteBook {
+    pub fn new(secret_key: RistrettoSecretKey, ttl: Duration, max_quotes: usize) -> Self {
+        Self {
+            secret_key,
+            ttl,
+            max_quotes,
+            quotes: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub async fn issue(
+        &self,
+        position: Position,
+    ) -> Result<(Quote, QuoteSignature), anyhow::Error> {
+        let now = unix_timestamp();
+        let quote = Quote {
+            quote_id: Uuid::new_v4(),
+            position,
+            expires_at: now + self.ttl.as_secs(),
+        };
+        let signature = self.sign(&quote)?;
+
+        let mut quotes = self.quotes.write().await;
+        // nobody can use the expired quotes, so we don't need to keep them
+        quotes.retain(|_, q| q.expires_at > now);
+        // otherwise anyone could fill our memory by asking for quotes
+        ensure!(
+            quotes.len() < self.max_quotes,
+            "There are too many quotes in use, try again later"
+        );
+        quotes.insert(quote.quote_id, quote.clone());
+
+        Ok((quote, signature))
+    }
+
+    // a quote can only be used once and exactly for the quoted amounts
+    pub async fn redeem(
+        &self,
+        quote_id: &QuoteId,
+        position: &Position,
+    ) -> Result<(), anyhow::Error> {
+        let mut quotes = self.quotes.write().await;
+        let quote = quotes.get(quote_id).ok_or_else(|| {
+            anyhow!(
+                "The quote '{}' does not exist or was already used",
+                quote_id
+            )
+        })?;
+        ensure!(
+            unix_timestamp() < quote.expires_at,
+            "The quote '{}' expired",
+            quote_id
+        );
+        ensure!(
+            &quote.position == position,
+            "The proposal does not match the quote '{}'",
+            quote_id
+        );
+        quotes.remove(quote_id);
+        Ok(())
+    }
+
+    fn sign(&self, quote: &Quote) -> Result<QuoteSignature, anyhow::Error> {
+        let signature = RistrettoSchnorr::sign_message(
+            &self.secret_key,
+            quote.message().as_bytes(),
+            &mut OsRng,
+        )
+        .map_err(|e| anyhow!("Could not sign the quote: {}", e))?;
+        Ok(QuoteSignature {
+            public_nonce: signature.get_public_nonce().to_hex(),
+            signature: signature.get_signature().to_hex(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tari_crypto::{
+        keys::{PublicKey, SecretKey},
+        ristretto::RistrettoPublicKey,
+    };
+
+    fn new_book(ttl: Duration) -> QuoteBook {
+        QuoteBook::new(RistrettoSecretKey::random(&mut OsRng), ttl, 10)
+    }
+
+    fn position() -> Position {
+        Position {
+            provided_token: "tari".to_string(),
+            provided_token_balance: 10,
+            requested_token: "eth.wei".to_string(),
+            requested_token_balance: 100,
+        }
+    }
+
+    #[tokio::test]
+    async fn it_signs_the_quotes() {
+        let book = new_book(Duration::from_secs(60));
+        let (quote, signature) = book.issue(position()).await.unwrap();
+
+        let signature = RistrettoSchnorr::new(
+            RistrettoPublicKey::from_hex(&signature.public_nonce).unwrap(),
+            RistrettoSecretKey::from_hex(&signature.signature).unwrap(),
+        );
+        let public_key = RistrettoPublicKey::from_secret_key(&book.secret_key);
+        assert!(signature.verify_message(&public_key, quote.message().as_bytes()));
+    }
+
+    #[tokio::test]
+    async fn it_does_not_redeem_a_quote_twice() {
+        let book = new_book(Duration::from_secs(60));
+        let (quote, _) = book.issue(position()).await.unwrap();
+
+        book.redeem(&quote.quote_id, &position()).await.unwrap();
+        assert!(book.redeem(&quote.quote_id, &position()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_does_not_redeem_expired_quotes() {
+        let book = new_book(Duration::ZERO);
+        let (quote, _) = book.issue(position()).await.unwrap();
+
+        let err = book.redeem(&quote.quote_id, &position()).await.unwrap_err();
+        assert!(err.to_string().contains("expired"));
+    }
+
+    #[tokio::test]
+    async fn it_only_redeems_the_quoted_amounts() {
+        let book = new_book(Duration::from_secs(60));
+        let (quote, _) = book.issue(position()).await.unwrap();
+
+        let mut other_position = position();
+        other_position.requested_token_balance += 1;
+        assert!(book.redeem(&quote.quote_id, &other_position).await.is_err());
+        // a rejected proposal does not consume the quote
+        book.redeem(&quote.quote_id, &position()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_limits_the_quotes_in_use() {
+        let book = new_book(Duration::from_secs(60));
+        for _ in 0..10 {
+            book.issue(position()).await.unwrap();
+        }
+        let err = book.issue(position()).await.unwrap_err();
+        assert!(err.to_string().contains("too many quotes"));
+
+        // the expired quotes do not count
+        let book = QuoteBook::new(RistrettoSecretKey::random(&mut OsRng), Duration::ZERO, 1);
+        book.issue(position()).await.unwrap();
+        book.issue(position()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_does_not_redeem_unknown_quotes() {
+        let book = new_book(Duration::from_secs(60));
+        assert!(book.redeem(&Uuid::new_v4(), &position()).await.is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use std::{sync::Arc, time::Duration};
+
+use log::{error, info};
+use tokio::time;
+
+use crate::swap_manager::{SwapId, SwapManager};
+
+const LOG_TARGET: &str = "liquidity_daemon::swap_checks";
+
+// The recurring checks over the ongoing swaps, each one returns the swaps that it changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapCheck {
+    // learns the preimages revealed by the clients in our contracts,
+    // so we can withdraw their funds even if they never push the preimage to us
+    CompleteRevealedSwaps,
+    // recovers our funds from the swaps that expired
+    RefundExpiredSwaps,
+    // finds out the result of the locks of our funds that we could not confirm
+    ReconcileUnconfirmedLocks,
+    // cleans up the proposals that the clients never funded
+    ExpireAbandonedProposals,
+    RemoveExpiredProposals,
+}
+
+impl SwapCheck {
+    async fn run(self, swap_manager: &SwapManager) -> Result<Vec<SwapId>, anyhow::Error> {
+        match self {
+            SwapCheck::CompleteRevealedSwaps => swap_manager.complete_revealed_swaps().await,
+            SwapCheck::RefundExpiredSwaps => swap_manager.refund_expired_swaps().await,
+            SwapCheck::ReconcileUnconfirmedLocks => {
+                swap_manager.reconcile_unconfirmed_locks().await
+            }
+            SwapCheck::ExpireAbandonedProposals => swap_manager.expire_abandoned_proposals().await,
+            SwapCheck::RemoveExpiredProposals => swap_manager.remove_expired_proposals().await,
+        }
+    }
+
+    // what happened to the swaps returned by the check
+    fn outcome(self) -> &'static str {
+        match self {
+            SwapCheck::CompleteRevealedSwaps => "revealed swaps completed",
+            SwapCheck::RefundExpiredSwaps => "expired swaps refunded",
+            SwapCheck::ReconcileUnconfirmedLocks => "unconfirmed locks reconciled",
+            SwapCheck::ExpireAbandonedProposals => "abandoned proposals expired",
+            SwapCheck::RemoveExpiredProposals => "expired proposals removed",
+        }
+    }
+}
+
+// Runs its checks in order at a fixed interval, a failed check does not prevent the rest
+pub struct SwapCheckRunner {
+    name: &'static str,
+    swap_manager: Arc<SwapManager>,
+    interval: Duration,
+    checks: Vec<SwapCheck>,
+}
+
+impl SwapCheckRunner {
+    pub fn new(
+        name: &'static str,
+        swap_manager: Arc<SwapManager>,
+        interval: Duration,
+        checks: Vec<SwapCheck>,
+    ) -> Self {
+        Self {
+            name,
+            swap_manager,
+            interval,
+            checks,
+        }
+    }
+
+    pub async fn run(self) {
+        let mut interval = time::interval(self.interval);
+        loop {
+            interval.tick().await;
+
+            for check in &self.checks {
+                match check.run(&self.swap_manager).await {
+                    Ok(swaps) if !swaps.is_empty() => info!(
+                        target: LOG_TARGET,
+                        "{}: {} {}",
+                        self.name,
+                        swaps.len(),
+                        check.outcome()
+                    ),
+                    Ok(_) => {}
+                    Err(e) => error!(
+                        target: LOG_TARGET,
+                        "{}: could not run the {:?} check: {}", self.name, check, e
+                    ),
+                }
+            }
+        }
+    }
+}
@@ -1,32 +1,49 @@
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::config::Config;
+use crate::config::ProposalConfig;
 use crate::position_manager::PositionManager;
+use crate::quote_book::{Quote, QuoteBook, QuoteId, QuoteSignature};
+use crate::swap_state::{unix_timestamp, SwapFilter, SwapState, SwapStatus};
+use crate::swap_store::SwapStore;
+use crate::timelock_policy::TimelockPolicy;
+use crate::token::{Network, TokenRegistry};
 use anyhow::anyhow;
 use anyhow::bail;
-use ethereum::EthereumContractManager;
-use ethers::types::Address;
+use anyhow::ensure;
 use ethers::utils::hex;
+use htlc::{ContractState, ContractStatus, HtlcBackend, HtlcError};
+use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
-use std::collections::HashMap;
-use tari::contract::TariContractManager;
-use tari_crypto::ristretto::RistrettoPublicKey;
-use tari_crypto::tari_utilities::hex::Hex;
-use tari_template_lib::prelude::ComponentAddress;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, OwnedMutexGuard, RwLock};
 use uuid::Uuid;
 
-pub type ContractId = String;
-pub type Preimage = [u8; 32];
-pub type Hashlock = [u8; 32];
+const LOG_TARGET: &str = "liquidity_daemon::swap_manager";
+const ABANDONED_PROPOSAL_REASON: &str = "The client did not lock their funds before the deadline";
+
+pub use htlc::{ContractId, HashFunction, Hashlock, Preimage};
+
+// the contract operations of each token, indexed by token id and each one behind its own lock
+pub type HtlcBackends = HashMap<String, RwLock<Box<dyn HtlcBackend>>>;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Proposal {
-    client_address: String,
-    hashlock: Hashlock,
-    position: Position,
+    // the receiver of our contract, in the network of the requested token
+    pub client_address: String,
+    // the sender of the client contract, in the network of the provided token
+    #[serde(default)]
+    pub client_sender_address: String,
+    pub hashlock: Hashlock,
+    // the one used by the client to create the hashlock, both networks of the swap must support it
+    #[serde(default)]
+    pub hash_function: HashFunction,
+    pub position: Position,
+    // the position must match exactly the quoted one
+    #[serde(default)]
+    pub quote_id: Option<QuoteId>,
 }
 
 #[serde_as]
@@ -40,8 +57,8 @@ pub struct Position {
     pub requested_token_balance: u64,
 }
 
-impl From<Position> for tari::liquidity::Position {
-    fn from(pos: Position) -> Self {
+impl From<tari::liquidity::Position> for Position {
+    fn from(pos: tari::liquidity::Position) -> Self {
         Self {
             provided_token: pos.provided_token,
             provided_token_balance: pos.provided_token_balance,
@@ -51,74 +68,286 @@ impl From<Position> for tari::liquidity::Position {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-enum SwapState {
-    NotStarted(Proposal),
-    Pending(PendingSwap),
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct PendingSwap {
-    client_contract_id: ContractId,
-    our_contract_id: ContractId,
-    proposal: Proposal,
+impl From<Position> for tari::liquidity::Position {
+    fn from(pos: Position) -> Self {
+        Self {
+            provided_token: pos.provided_token,
+            provided_token_balance: pos.provided_token_balance,
+            requested_token: pos.requested_token,
+            requested_token_balance: pos.requested_token_balance,
+        }
+    }
 }
 
 pub type SwapId = Uuid;
-type SwapKvMap = HashMap<SwapId, SwapState>;
 
 pub struct SwapManager {
-    config: Config,
-    // TODO: use a database to not lose ongoing swap information on restarts
-    swaps: Arc<RwLock<SwapKvMap>>,
+    token_registry: TokenRegistry,
+    swaps: Arc<RwLock<Box<dyn SwapStore>>>,
+    // the store is only locked to read and save the states, each swap has its own lock for the rest
+    swap_locks: SwapLocks,
     position_manager: PositionManager,
-    eth_manager: EthereumContractManager,
-    tari_manager: Arc<RwLock<TariContractManager>>,
+    backends: HtlcBackends,
+    timelock_policy: TimelockPolicy,
+    // only when we can sign the quotes
+    quote_book: Option<QuoteBook>,
+    proposal_config: ProposalConfig,
 }
 
 impl SwapManager {
     pub fn new(
-        config: Config,
+        token_registry: TokenRegistry,
         position_manager: PositionManager,
-        eth_manager: EthereumContractManager,
-        tari_manager: TariContractManager,
+        backends: HtlcBackends,
+        swap_store: Box<dyn SwapStore>,
+        timelock_policy: TimelockPolicy,
+        quote_book: Option<QuoteBook>,
+        proposal_config: ProposalConfig,
     ) -> Self {
         Self {
-            config,
-            swaps: Arc::new(RwLock::new(HashMap::new())),
+            token_registry,
+            swaps: Arc::new(RwLock::new(swap_store)),
+            swap_locks: SwapLocks::default(),
             position_manager,
-            eth_manager,
-            tari_manager: Arc::new(RwLock::new(tari_manager)),
+            backends,
+            timelock_policy,
+            quote_book,
+            proposal_config,
+        }
+    }
+
+    // reloads the swaps that were ongoing when the daemon stopped, so clients can continue them
+    pub async fn resume_swaps(&self) -> Result<usize, anyhow::Error> {
+        let write_guard = self.swaps.write().await;
+        let ongoing_swaps = write_guard.list_ongoing()?;
+
+        for (swap_id, mut state) in ongoing_swaps.clone() {
+            info!(
+                target: LOG_TARGET,
+                "Resuming swap {} in status {:?}",
+                swap_id,
+                state.status()
+            );
+            if state.status() == SwapStatus::ClientLocked {
+                self.resume_interrupted_lock(&swap_id, &mut state)?;
+                write_guard.save(&swap_id, &state)?;
+            }
+            // our funds are still locked in the swap (or may be), so they are not part of our balance either
+            if state.status().has_provider_funds_locked() || state.unconfirmed_lock.is_some() {
+                let position = state.proposal.position.clone().into();
+                if let Err(e) = self.position_manager.fill(&position).await {
+                    error!(
+                        target: LOG_TARGET,
+                        "Could not reserve the funds of swap {}: {}", swap_id, e
+                    );
+                }
+            }
+        }
+        drop(write_guard);
+        self.sync_inventory().await?;
+
+        let reconciled_swaps = self.reconcile_unconfirmed_locks().await?;
+        if !reconciled_swaps.is_empty() {
+            info!(
+                target: LOG_TARGET,
+                "Reconciled {} interrupted locks",
+                reconciled_swaps.len()
+            );
+        }
+
+        Ok(ongoing_swaps.len())
+    }
+
+    // we stopped while locking our funds, so we do not know if our contract was created
+    fn resume_interrupted_lock(
+        &self,
+        swap_id: &SwapId,
+        state: &mut SwapState,
+    ) -> Result<(), anyhow::Error> {
+        if state.unconfirmed_lock.is_some() {
+            return Ok(());
         }
+        // our timelock is recorded before locking, so without it we never sent the transaction
+        if state.our_timelock.is_none() {
+            warn!(
+                target: LOG_TARGET,
+                "Swap {} was interrupted before locking our funds", swap_id
+            );
+            state.fail("The daemon stopped before locking our funds".to_string())?;
+            return Ok(());
+        }
+        warn!(
+            target: LOG_TARGET,
+            "Swap {} was interrupted while locking our funds, looking for our contract", swap_id
+        );
+        state.lock_unconfirmed("The daemon stopped while locking our funds".to_string())?;
+        Ok(())
     }
 
+    // we may have given away part of the configured balances in the swaps completed before a restart
+    async fn sync_inventory(&self) -> Result<(), anyhow::Error> {
+        for token_id in self.position_manager.provided_tokens().await {
+            let funds = self.get_backend(&token_id)?.write().await.balance().await?;
+            self.position_manager
+                .limit_available(&token_id, funds)
+                .await?;
+        }
+        Ok(())
+    }
+
+    // the best amount that we can provide for the client funds, signed so they can hold us to it
+    pub async fn get_quote(
+        &self,
+        provided_token: String,
+        provided_token_balance: u64,
+        requested_token: String,
+    ) -> Result<(Quote, QuoteSignature), anyhow::Error> {
+        let quote_book = self.get_quote_book()?;
+        let position = self
+            .position_manager
+            .quote(provided_token, provided_token_balance, requested_token)
+            .await?;
+        quote_book.issue(position.into()).await
+    }
+
+    // returns the deadline for the client to lock their funds along with the swap information
     pub async fn request_swap(
         &self,
         proposal: Proposal,
-    ) -> Result<(SwapId, String), anyhow::Error> {
-        if self
-            .position_manager
-            .is_swap_proposal_valid(&proposal.position.clone().into())
-            .await
-        {
-            let swap_id = Uuid::new_v4();
-            let provider_address = self.get_provider_address(&proposal)?;
-            let swap_state = SwapState::NotStarted(proposal);
-            let mut guard = self.swaps.write().await;
-            guard.insert(swap_id, swap_state);
-            Ok((swap_id, provider_address))
-        } else {
-            bail!("Invalid proposal");
+    ) -> Result<(SwapId, String, u64), anyhow::Error> {
+        // a quote was already priced by us, the balance is only checked again when locking our funds
+        if proposal.quote_id.is_none() {
+            self.position_manager
+                .validate_swap_proposal(&proposal.position.clone().into())
+                .await?;
+        }
+        self.validate_hash_function(&proposal).await?;
+        ensure!(
+            !proposal.client_sender_address.is_empty(),
+            "The client sender address is required"
+        );
+
+        let swap_id = Uuid::new_v4();
+        let provider_address = self.get_provider_address(&proposal).await?;
+
+        let write_guard = self.swaps.write().await;
+        let now = unix_timestamp();
+        // otherwise the clients could grow the swap store without bound,
+        // and the total is capped too as anyone can use as many client addresses as they want
+        let open_proposals = list_open_proposals(write_guard.as_ref(), now)?;
+        ensure!(
+            open_proposals.len() < self.proposal_config.max_open,
+            "There are too many proposals waiting for their funds, try again later"
+        );
+        let client_proposals = open_proposals
+            .iter()
+            .filter(|state| state.proposal.client_address == proposal.client_address)
+            .count();
+        ensure!(
+            client_proposals < self.proposal_config.max_open_per_client,
+            "The client already has {} proposals waiting for their funds",
+            client_proposals
+        );
+        // the quote is only consumed once the proposal is accepted
+        if let Some(quote_id) = &proposal.quote_id {
+            self.get_quote_book()?
+                .redeem(quote_id, &proposal.position)
+                .await?;
+        }
+        let lock_deadline = now + self.proposal_config.lock_deadline_secs;
+        let swap_state = SwapState::new(proposal, lock_deadline);
+        write_guard.save(&swap_id, &swap_state)?;
+        Ok((swap_id, provider_address, lock_deadline))
+    }
+
+    // expires the proposals whose clients did not lock their funds before the deadline
+    pub async fn expire_abandoned_proposals(&self) -> Result<Vec<SwapId>, anyhow::Error> {
+        let abandoned_swaps: Vec<SwapId> = {
+            let read_guard = self.swaps.read().await;
+            let now = unix_timestamp();
+            read_guard
+                .list_ongoing()?
+                .into_iter()
+                .filter(|(_, state)| state.is_abandoned(now))
+                .map(|(swap_id, _)| swap_id)
+                .collect()
+        };
+
+        let mut expired_swaps = vec![];
+        for swap_id in abandoned_swaps {
+            let _swap_lock = self.swap_locks.lock(&swap_id).await;
+            // the client could have locked their funds since we listed the swap
+            let mut swap_state = self.get_swap_state(&swap_id).await?;
+            if !swap_state.is_abandoned(unix_timestamp()) {
+                continue;
+            }
+            swap_state.expire(ABANDONED_PROPOSAL_REASON.to_string())?;
+            self.save_swap_state(&swap_id, &swap_state).await?;
+            expired_swaps.push(swap_id);
+        }
+
+        Ok(expired_swaps)
+    }
+
+    // deletes the expired proposals after the retention period, if they never had any funds locked
+    pub async fn remove_expired_proposals(&self) -> Result<Vec<SwapId>, anyhow::Error> {
+        let write_guard = self.swaps.write().await;
+        let retention_limit =
+            unix_timestamp().saturating_sub(self.proposal_config.expired_retention_secs);
+
+        let mut removed_swaps = vec![];
+        for (swap_id, swap_state) in write_guard.list()? {
+            let last_transition = swap_state.last_transition();
+            // the client may still need the swap to refund their contract
+            if last_transition.status == SwapStatus::Expired
+                && swap_state.client_contract_id.is_none()
+                && last_transition.timestamp <= retention_limit
+            {
+                write_guard.remove(&swap_id)?;
+                removed_swaps.push(swap_id);
+            }
         }
+
+        Ok(removed_swaps)
+    }
+
+    // all the operations over a token are routed to its backend
+    fn get_backend(&self, token_id: &str) -> Result<&RwLock<Box<dyn HtlcBackend>>, anyhow::Error> {
+        let token = self.token_registry.get(token_id)?;
+        self.backends
+            .get(&token.id)
+            .ok_or_else(|| anyhow!("There is no backend for the token '{}'", token.id))
+    }
+
+    fn get_quote_book(&self) -> Result<&QuoteBook, anyhow::Error> {
+        self.quote_book.as_ref().ok_or_else(|| {
+            anyhow!("Quotes are disabled, as the Tari private key is not configured")
+        })
     }
 
-    fn get_provider_address(&self, proposal: &Proposal) -> Result<String, anyhow::Error> {
-        // TODO: create enums and parsing logic for each type of token
-        match proposal.position.provided_token.as_str() {
-            "eth.wei" => Ok(self.config.ethereum.account_address.clone()),
-            "tari" => Ok(self.config.tari.account_component.clone()),
-            _ => bail!("Invalid token type"),
+    // both contracts of the swap are locked with the same hashlock
+    async fn validate_hash_function(&self, proposal: &Proposal) -> Result<(), anyhow::Error> {
+        let token_ids = [
+            &proposal.position.provided_token,
+            &proposal.position.requested_token,
+        ];
+        for token_id in token_ids {
+            let hash_function = self.get_backend(token_id)?.read().await.hash_function();
+            ensure!(
+                hash_function == proposal.hash_function,
+                "The token '{}' does not support the {:?} hash function",
+                token_id,
+                proposal.hash_function
+            );
         }
+        Ok(())
+    }
+
+    // the client must lock the funds for the address that we use in the provided token backend
+    async fn get_provider_address(&self, proposal: &Proposal) -> Result<String, anyhow::Error> {
+        let backend = self.get_backend(&proposal.position.provided_token)?;
+        let address = backend.read().await.address();
+        Ok(address)
     }
 
     pub async fn request_lock_funds(
@@ -127,26 +356,89 @@ impl SwapManager {
         contract_id: ContractId,
     ) -> Result<ContractId, anyhow::Error> {
         let swap_id = SwapId::from_str(&swap_id)?;
-        let swap_state = self.get_swap_state(&swap_id).await?;
+        // held until the end, so concurrent requests cannot lock our funds twice
+        let _swap_lock = self.swap_locks.lock(&swap_id).await;
+        let mut swap_state = self.get_swap_state(&swap_id).await?;
+
+        ensure!(
+            swap_state.status() == SwapStatus::Proposed,
+            "Funds already locked"
+        );
+        // the supervisor may not have expired the proposal yet
+        if swap_state.is_abandoned(unix_timestamp()) {
+            swap_state.expire(ABANDONED_PROPOSAL_REASON.to_string())?;
+            self.save_swap_state(&swap_id, &swap_state).await?;
+            bail!("The deadline to lock the funds has passed");
+        }
+        let proposal = swap_state.proposal.clone();
+        let (client_timelock, client_window) =
+            self.validate_contract_id(&contract_id, &proposal).await?;
+        let our_timelock = self.get_our_timelock(&proposal, client_window).await?;
+        swap_state.client_locked(contract_id.clone(), client_timelock)?;
+        swap_state.our_timelock = Some(our_timelock);
+        {
+            // the same locked funds cannot be used to pay for multiple swaps,
+            // checked along with the save as the other swaps may be claiming the contract concurrently
+            let write_guard = self.swaps.write().await;
+            ensure!(
+                !is_client_contract_used(write_guard.as_ref(), &contract_id)?,
+                "The contract is already used in another swap"
+            );
+            write_guard.save(&swap_id, &swap_state)?;
+        }
 
-        let mut write_guard = self.swaps.write().await;
-
-        match swap_state {
-            SwapState::NotStarted(proposal) => {
-                self.validate_contract_id(&contract_id, &proposal).await?;
-                let our_contract_id = self.create_lock_contract(&proposal).await?;
-                write_guard.insert(
-                    swap_id,
-                    SwapState::Pending(PendingSwap {
-                        client_contract_id: contract_id,
-                        our_contract_id: our_contract_id.clone(),
-                        proposal: proposal.clone(),
-                    }),
+        // from now on the client can only get their funds back by waiting for their timelock
+        let our_contract_id = match self.lock_our_funds(&proposal, our_timelock).await {
+            Ok(our_contract_id) => our_contract_id,
+            // the supervisor will find out if our funds were locked, see "reconcile_unconfirmed_locks"
+            Err(e) if is_unconfirmed(&e) => {
+                swap_state.lock_unconfirmed(e.to_string())?;
+                self.save_swap_state(&swap_id, &swap_state).await?;
+                bail!(
+                    "Our funds may be locked, but the transaction is not confirmed yet: {}",
+                    e
                 );
-                Ok(our_contract_id)
             }
-            _ => bail!("Funds alredy locked"),
+            Err(e) => {
+                swap_state.fail(format!("Could not lock our funds: {}", e))?;
+                self.save_swap_state(&swap_id, &swap_state).await?;
+                return Err(e);
+            }
+        };
+        swap_state.provider_locked(our_contract_id.clone(), our_timelock)?;
+        self.save_swap_state(&swap_id, &swap_state).await?;
+        let token = self
+            .token_registry
+            .get(&proposal.position.requested_token)?;
+        info!(
+            target: LOG_TARGET,
+            "Locked {} in our contract '{}' for swap {}",
+            token.format_amount(proposal.position.requested_token_balance),
+            our_contract_id,
+            swap_id
+        );
+
+        Ok(our_contract_id)
+    }
+
+    // other swaps may have taken the position balance since the proposal was accepted,
+    // so the balance is reserved before locking, and concurrent swaps cannot lock it twice
+    async fn lock_our_funds(
+        &self,
+        proposal: &Proposal,
+        timelock: u64,
+    ) -> Result<ContractId, anyhow::Error> {
+        let position = proposal.position.clone().into();
+        self.position_manager.fill(&position).await?;
+
+        let result = self.create_lock_contract(proposal, timelock).await;
+        // an unconfirmed lock keeps the balance reserved, as the funds may be locked anyway
+        if matches!(&result, Err(e) if !is_unconfirmed(e)) {
+            if let Err(e) = self.position_manager.cancel_fill(&position).await {
+                error!(target: LOG_TARGET, "Could not release the reserved balance: {}", e);
+            }
         }
+        result
     }
 
     pub async fn push_preimage(
@@ -154,94 +446,646 @@ impl SwapManager {
         swap_id: String,
         preimage: Preimage,
     ) -> Result<(), anyhow::Error> {
-        // TODO: we need a constant polling process watching the network to not rely on the client sending the preimage
+        // the chain watcher will also complete the swap if the client never calls this method
         let swap_id = SwapId::from_str(&swap_id)?;
-        let swap_state = self.get_swap_state(&swap_id).await?;
+        // held until the end, so the chain watcher cannot withdraw the same funds concurrently
+        let _swap_lock = self.swap_locks.lock(&swap_id).await;
+        let mut swap_state = self.get_swap_state(&swap_id).await?;
+
+        match swap_state.status() {
+            SwapStatus::ProviderLocked => {
+                // the client's contract would also reject it, but only after we pay for the transaction
+                let proposal = &swap_state.proposal;
+                ensure!(
+                    proposal.hash_function.verify(&preimage, &proposal.hashlock),
+                    "The preimage does not match the hashlock"
+                );
+                self.withdraw_funds(&swap_state, preimage).await?;
+                swap_state.preimage_revealed(preimage)?;
+                self.complete_swap(&swap_id, &mut swap_state).await
+            }
+            SwapStatus::Proposed | SwapStatus::ClientLocked => bail!("Swap has not started yet"),
+            SwapStatus::PreimageRevealed => bail!("The preimage was already revealed"),
+            status => bail!("Swap already finished with status {:?}", status),
+        }
+    }
 
-        let mut write_guard = self.swaps.write().await;
+    async fn complete_swap(
+        &self,
+        swap_id: &SwapId,
+        swap_state: &mut SwapState,
+    ) -> Result<(), anyhow::Error> {
+        swap_state.complete()?;
+        self.save_swap_state(swap_id, swap_state).await?;
+        let position = swap_state.proposal.position.clone().into();
+        self.position_manager.settle_fill(&position).await?;
+        Ok(())
+    }
 
-        match swap_state {
-            SwapState::Pending(pending) => {
-                self.withdraw_funds(&pending, preimage).await?;
-                write_guard.remove(&swap_id);
-                // TODO: update published balances
-                Ok(())
+    // completes all the pending swaps where the client already revealed the preimage on-chain
+    pub async fn complete_revealed_swaps(&self) -> Result<Vec<SwapId>, anyhow::Error> {
+        let pending_swaps = self.get_pending_swap_ids().await?;
+
+        let mut completed_swaps = vec![];
+        for swap_id in pending_swaps {
+            // a failure in one swap should not prevent the completion of the others
+            match self.complete_if_revealed(&swap_id).await {
+                Ok(true) => completed_swaps.push(swap_id),
+                Ok(false) => {}
+                Err(e) => error!(target: LOG_TARGET, "Could not complete swap {}: {}", swap_id, e),
             }
-            _ => bail!("Swap has not started yet"),
         }
+
+        Ok(completed_swaps)
+    }
+
+    async fn complete_if_revealed(&self, swap_id: &SwapId) -> Result<bool, anyhow::Error> {
+        let _swap_lock = self.swap_locks.lock(swap_id).await;
+        // the client could have pushed the preimage since we listed the swap
+        let mut swap_state = self.get_swap_state(swap_id).await?;
+
+        match swap_state.status() {
+            SwapStatus::ProviderLocked => {
+                let preimage = match self.get_revealed_preimage(&swap_state).await? {
+                    Some(preimage) => preimage,
+                    None => return Ok(false),
+                };
+                // we record the preimage first, so a failed withdrawal can be retried later
+                swap_state.preimage_revealed(preimage)?;
+                self.save_swap_state(swap_id, &swap_state).await?;
+            }
+            SwapStatus::PreimageRevealed => {}
+            _ => return Ok(false),
+        }
+
+        let preimage = swap_state
+            .preimage
+            .ok_or_else(|| anyhow!("The preimage of swap {} is missing", swap_id))?;
+        self.withdraw_funds(&swap_state, preimage).await?;
+        info!(
+            target: LOG_TARGET,
+            "Completed swap {}, the client revealed the preimage in our contract '{}'",
+            swap_id,
+            our_contract_id(&swap_state)?
+        );
+        self.complete_swap(swap_id, &mut swap_state).await?;
+
+        Ok(true)
+    }
+
+    // refunds our locked funds in all the pending swaps where the client never revealed the preimage
+    pub async fn refund_expired_swaps(&self) -> Result<Vec<SwapId>, anyhow::Error> {
+        let pending_swaps = self.get_pending_swap_ids().await?;
+
+        let mut refunded_swaps = vec![];
+        for swap_id in pending_swaps {
+            // a failure in one swap should not prevent the refund of the others
+            match self.refund_if_expired(&swap_id).await {
+                Ok(true) => refunded_swaps.push(swap_id),
+                Ok(false) => {}
+                Err(e) => error!(target: LOG_TARGET, "Could not refund swap {}: {}", swap_id, e),
+            }
+        }
+
+        Ok(refunded_swaps)
+    }
+
+    async fn refund_if_expired(&self, swap_id: &SwapId) -> Result<bool, anyhow::Error> {
+        let _swap_lock = self.swap_locks.lock(swap_id).await;
+        // the swap could have been completed since we listed it
+        let mut swap_state = self.get_swap_state(swap_id).await?;
+
+        match swap_state.status() {
+            SwapStatus::ProviderLocked => {
+                if !self.is_our_contract_refundable(&swap_state).await? {
+                    return Ok(false);
+                }
+                let reason = format!(
+                    "The timelock of our contract '{}' expired without the client revealing the preimage",
+                    our_contract_id(&swap_state)?
+                );
+                // we record the refund first, so a failed refund can be retried later
+                swap_state.start_refund(reason)?;
+                self.save_swap_state(swap_id, &swap_state).await?;
+            }
+            SwapStatus::Refunding => {}
+            _ => return Ok(false),
+        }
+
+        self.refund_funds(&swap_state).await?;
+        info!(
+            target: LOG_TARGET,
+            "Refunded swap {}: {}",
+            swap_id,
+            swap_state.last_transition().reason.as_deref().unwrap_or_default()
+        );
+        swap_state.refunded()?;
+        self.save_swap_state(swap_id, &swap_state).await?;
+        let position = swap_state.proposal.position.clone().into();
+        self.position_manager.cancel_fill(&position).await?;
+
+        Ok(true)
+    }
+
+    // finds out on-chain if our funds were locked in the swaps where we could not confirm it
+    pub async fn reconcile_unconfirmed_locks(&self) -> Result<Vec<SwapId>, anyhow::Error> {
+        let unconfirmed_swaps: Vec<SwapId> = {
+            let read_guard = self.swaps.read().await;
+            read_guard
+                .list_ongoing()?
+                .into_iter()
+                .filter(|(_, state)| state.unconfirmed_lock.is_some())
+                .map(|(swap_id, _)| swap_id)
+                .collect()
+        };
+
+        let mut reconciled_swaps = vec![];
+        for swap_id in unconfirmed_swaps {
+            // a failure in one swap should not prevent the reconciliation of the others
+            match self.reconcile_lock(&swap_id).await {
+                Ok(true) => reconciled_swaps.push(swap_id),
+                Ok(false) => {}
+                Err(e) => error!(target: LOG_TARGET, "Could not reconcile swap {}: {}", swap_id, e),
+            }
+        }
+
+        Ok(reconciled_swaps)
+    }
+
+    async fn reconcile_lock(&self, swap_id: &SwapId) -> Result<bool, anyhow::Error> {
+        let _swap_lock = self.swap_locks.lock(swap_id).await;
+        let mut swap_state = self.get_swap_state(swap_id).await?;
+        if swap_state.status() != SwapStatus::ClientLocked || swap_state.unconfirmed_lock.is_none()
+        {
+            return Ok(false);
+        }
+        let our_timelock = swap_state.our_timelock.ok_or_else(|| {
+            anyhow!(
+                "The timelock of our contract in swap {} is missing",
+                swap_id
+            )
+        })?;
+
+        // the chain time goes first, so a lock confirmed in between is always found
+        let chain_time = self.get_our_chain_time(&swap_state).await?;
+        match self.find_our_contract(&swap_state, our_timelock).await? {
+            Some(our_contract_id) => {
+                info!(
+                    target: LOG_TARGET,
+                    "Found our contract '{}' for swap {}, the lock of our funds was confirmed",
+                    our_contract_id,
+                    swap_id
+                );
+                swap_state.provider_locked(our_contract_id, our_timelock)?;
+                self.save_swap_state(swap_id, &swap_state).await?;
+            }
+            // the networks reject the contracts whose timelock already passed, so ours can no longer appear
+            None if chain_time >= our_timelock => {
+                let reason = format!(
+                    "Our funds were never locked, the lock was not confirmed before its timelock {}",
+                    our_timelock
+                );
+                info!(target: LOG_TARGET, "Expired swap {}: {}", swap_id, reason);
+                swap_state.expire(reason)?;
+                self.save_swap_state(swap_id, &swap_state).await?;
+                let position = swap_state.proposal.position.clone().into();
+                self.position_manager.cancel_fill(&position).await?;
+            }
+            None => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    async fn get_pending_swap_ids(&self) -> Result<Vec<SwapId>, anyhow::Error> {
+        let read_guard = self.swaps.read().await;
+        let pending_swaps = read_guard
+            .list_ongoing()?
+            .into_iter()
+            .filter(|(_, state)| state.status().has_provider_funds_locked())
+            .map(|(swap_id, _)| swap_id)
+            .collect();
+        Ok(pending_swaps)
+    }
+
+    pub async fn get_swap(&self, swap_id: String) -> Result<(SwapId, SwapState), anyhow::Error> {
+        let swap_id = SwapId::from_str(&swap_id)?;
+        let swap_state = self.get_swap_state(&swap_id).await?;
+        Ok((swap_id, swap_state))
     }
 
     async fn get_swap_state(&self, swap_id: &SwapId) -> Result<SwapState, anyhow::Error> {
         let read_guard = self.swaps.read().await;
         let state = read_guard
-            .get(swap_id)
+            .get(swap_id)?
             .ok_or_else(|| anyhow!("Invalid swap_id"))?;
-        Ok(state.to_owned())
+        Ok(state)
     }
 
-    async fn validate_contract_id(
+    async fn save_swap_state(
         &self,
-        _contract_id: &ContractId,
-        _proposal: &Proposal,
+        swap_id: &SwapId,
+        swap_state: &SwapState,
     ) -> Result<(), anyhow::Error> {
-        // TODO: implement on-chain validation of the contract id, to check that the client did lock the funds as expected
-
+        self.swaps.write().await.save(swap_id, swap_state)?;
         Ok(())
     }
 
-    async fn create_lock_contract(&self, proposal: &Proposal) -> Result<ContractId, anyhow::Error> {
-        // TODO: create enums and parsing logic for each type of token
-        match proposal.position.requested_token.as_str() {
-            "eth.wei" => {
-                let amount_wei = proposal.position.requested_token_balance;
-                let receiver = proposal.client_address.parse::<Address>()?;
-                let hashlock = proposal.hashlock;
-                // TODO: constant for timelocks
-                let timelock = 100;
-                let contract_id = self
-                    .eth_manager
-                    .new_contract(amount_wei.into(), receiver, hashlock, timelock)
-                    .await?;
-                Ok(hex::encode(contract_id))
-            }
-            "tari" => {
-                let mut write_guard = self.tari_manager.write().await;
-                let amount_tari: i64 = proposal.position.requested_token_balance.try_into()?;
-                let receiver = RistrettoPublicKey::from_hex(&proposal.client_address)?;
-                let hashlock = proposal.hashlock;
-                // TODO: constant for timelocks
-                let timelock = 100;
-                let contract_id = write_guard
-                    .create_lock_contract(amount_tari, receiver, hashlock, timelock)
-                    .await?;
-                Ok(contract_id.to_string())
-            }
-            _ => bail!("Invalid token type"),
+    // newest swaps first
+    pub async fn list_swaps(
+        &self,
+        filter: &SwapFilter,
+    ) -> Result<Vec<(SwapId, SwapState)>, anyhow::Error> {
+        let read_guard = self.swaps.read().await;
+        let mut swaps: Vec<(SwapId, SwapState)> = read_guard
+            .list()?
+            .into_iter()
+            .filter(|(_, state)| filter.matches(state))
+            .collect();
+        swaps.sort_by_key(|(_, state)| std::cmp::Reverse(state.created_at()));
+        Ok(swaps)
+    }
+
+    // checks on-chain that the client did lock the funds as expected in the proposal
+    // returns the timelock of the contract and the time left until the client can refund it
+    async fn validate_contract_id(
+        &self,
+        contract_id: &ContractId,
+        proposal: &Proposal,
+    ) -> Result<(u64, Duration), anyhow::Error> {
+        let token = self.token_registry.get(&proposal.position.provided_token)?;
+        let mut backend = self.get_backend(&token.id)?.write().await;
+        let contract = backend.get_contract_state(contract_id).await?;
+        let chain_time = backend.current_chain_time().await?;
+        let window = check_client_contract(
+            &contract,
+            proposal,
+            &backend.address(),
+            &self.timelock_policy,
+            token.network,
+            chain_time,
+        )?;
+
+        // the confidential amounts are hidden from everyone but us, as the receivers of the contract
+        if contract.confidential {
+            let expected_amount = proposal.position.provided_token_balance;
+            ensure!(
+                backend
+                    .verify_confidential_amount(contract_id, expected_amount)
+                    .await?,
+                "Invalid amount, the confidential funds of the contract do not add up to {}",
+                expected_amount
+            );
         }
+
+        Ok((contract.timelock, window))
+    }
+
+    // our contract must expire before the client's one, see "TimelockPolicy"
+    async fn get_our_timelock(
+        &self,
+        proposal: &Proposal,
+        client_window: Duration,
+    ) -> Result<u64, anyhow::Error> {
+        let token = self
+            .token_registry
+            .get(&proposal.position.requested_token)?;
+        let our_window = self.timelock_policy.provider_window(client_window)?;
+        let chain_time = self
+            .get_backend(&token.id)?
+            .write()
+            .await
+            .current_chain_time()
+            .await?;
+        self.timelock_policy
+            .provider_timelock(token.network, chain_time, our_window)
+    }
+
+    async fn create_lock_contract(
+        &self,
+        proposal: &Proposal,
+        timelock: u64,
+    ) -> Result<ContractId, anyhow::Error> {
+        let backend = self.get_backend(&proposal.position.requested_token)?;
+        let contract_id = backend
+            .write()
+            .await
+            .lock(
+                proposal.position.requested_token_balance,
+                &proposal.client_address,
+                proposal.hashlock,
+                timelock,
+            )
+            .await?;
+        Ok(contract_id)
+    }
+
+    // our contract, if the network already confirmed the lock of our funds
+    async fn find_our_contract(
+        &self,
+        swap_state: &SwapState,
+        timelock: u64,
+    ) -> Result<Option<ContractId>, anyhow::Error> {
+        let proposal = &swap_state.proposal;
+        let backend = self.get_backend(&proposal.position.requested_token)?;
+        let contract_id = backend
+            .write()
+            .await
+            .find_lock(
+                proposal.position.requested_token_balance,
+                &proposal.client_address,
+                proposal.hashlock,
+                timelock,
+            )
+            .await?;
+        Ok(contract_id)
+    }
+
+    async fn get_our_chain_time(&self, swap_state: &SwapState) -> Result<u64, anyhow::Error> {
+        let backend = self.get_backend(&swap_state.proposal.position.requested_token)?;
+        let chain_time = backend.write().await.current_chain_time().await?;
+        Ok(chain_time)
     }
 
     async fn withdraw_funds(
         &self,
-        pending_swap: &PendingSwap,
+        swap_state: &SwapState,
         preimage: Preimage,
     ) -> Result<(), anyhow::Error> {
-        // TODO: create enums and parsing logic for each type of token
-        match pending_swap.proposal.position.provided_token.as_str() {
-            "eth.wei" => {
-                let contract_id_hex = &pending_swap.client_contract_id.trim_start_matches("0x");
-                let contract_id: [u8; 32] = hex::decode(contract_id_hex)?
-                    .try_into()
-                    .map_err(|_| anyhow!("Invalid contract_id"))?;
-                self.eth_manager.withdraw(contract_id, preimage).await?;
-                Ok(())
-            }
-            "tari" => {
-                let mut write_guard = self.tari_manager.write().await;
-                let contract_id = ComponentAddress::from_str(&pending_swap.client_contract_id)?;
-                write_guard.withdraw(contract_id, preimage).await?;
-                Ok(())
-            }
-            _ => bail!("Invalid token type"),
+        let backend = self.get_backend(&swap_state.proposal.position.provided_token)?;
+        let mut backend = backend.write().await;
+        let contract_id = client_contract_id(swap_state)?;
+        // the transaction of a previous attempt may have been confirmed after we stopped waiting for it
+        if backend.get_contract_state(contract_id).await?.status == ContractStatus::Withdrawn {
+            return Ok(());
         }
+        backend.withdraw(contract_id, preimage).await?;
+        Ok(())
+    }
+
+    // the client reveals the preimage when withdrawing the funds from our contract
+    async fn get_revealed_preimage(
+        &self,
+        swap_state: &SwapState,
+    ) -> Result<Option<Preimage>, anyhow::Error> {
+        let backend = self.get_backend(&swap_state.proposal.position.requested_token)?;
+        let preimage = backend
+            .write()
+            .await
+            .get_preimage(our_contract_id(swap_state)?)
+            .await?;
+        Ok(preimage)
+    }
+
+    // our contract is always on the network of the token requested by the client
+    async fn is_our_contract_refundable(
+        &self,
+        swap_state: &SwapState,
+    ) -> Result<bool, anyhow::Error> {
+        let backend = self.get_backend(&swap_state.proposal.position.requested_token)?;
+        let is_refundable = backend
+            .write()
+            .await
+            .is_refundable(our_contract_id(swap_state)?)
+            .await?;
+        Ok(is_refundable)
+    }
+
+    async fn refund_funds(&self, swap_state: &SwapState) -> Result<(), anyhow::Error> {
+        let backend = self.get_backend(&swap_state.proposal.position.requested_token)?;
+        let mut backend = backend.write().await;
+        let contract_id = our_contract_id(swap_state)?;
+        if backend.get_contract_state(contract_id).await?.status == ContractStatus::Refunded {
+            return Ok(());
+        }
+        backend.refund(contract_id).await?;
+        Ok(())
+    }
+}
+
+// Serializes the operations over each swap, so the on-chain transactions of different swaps do not wait for each other
+#[derive(Default)]
+struct SwapLocks {
+    locks: std::sync::Mutex<HashMap<SwapId, Arc<Mutex<()>>>>,
+}
+
+impl SwapLocks {
+    async fn lock(&self, swap_id: &SwapId) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.locks.lock().unwrap();
+            // nobody holds or waits for the rest of the locks, so they are no longer needed
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            locks.entry(*swap_id).or_default().clone()
+        };
+        lock.lock_owned().await
+    }
+}
+
+// returns the time left until the client can refund their contract
+fn check_client_contract(
+    contract: &ContractState,
+    proposal: &Proposal,
+    our_address: &str,
+    timelock_policy: &TimelockPolicy,
+    network: Network,
+    chain_time: u64,
+) -> Result<Duration, anyhow::Error> {
+    // the addresses may differ in case, e.g. the checksummed Ethereum addresses
+    ensure!(
+        contract
+            .sender
+            .eq_ignore_ascii_case(&proposal.client_sender_address),
+        "Invalid sender, expected '{}' but found '{}'",
+        proposal.client_sender_address,
+        contract.sender
+    );
+
+    ensure!(
+        contract.receiver == our_address,
+        "Invalid receiver, expected '{}' but found '{}'",
+        our_address,
+        contract.receiver
+    );
+
+    // the confidential amounts can only be checked by the backend
+    let expected_amount = proposal.position.provided_token_balance;
+    ensure!(
+        contract.confidential || contract.amount == expected_amount,
+        "Invalid amount, expected {} but found {}",
+        expected_amount,
+        contract.amount
+    );
+
+    ensure!(
+        contract.hashlock == proposal.hashlock,
+        "Invalid hashlock, expected '{}' but found '{}'",
+        hex::encode(proposal.hashlock),
+        hex::encode(contract.hashlock)
+    );
+
+    ensure!(
+        contract.status == ContractStatus::Locked,
+        "The funds are no longer locked in the contract"
+    );
+
+    timelock_policy.client_window(network, contract.timelock, chain_time)
+}
+
+// the abandoned proposals do not count, even if the supervisor did not expire them yet
+fn list_open_proposals(
+    swap_store: &dyn SwapStore,
+    now: u64,
+) -> Result<Vec<SwapState>, anyhow::Error> {
+    let open_proposals = swap_store
+        .list_ongoing()?
+        .into_iter()
+        .map(|(_, state)| state)
+        .filter(|state| state.status() == SwapStatus::Proposed && !state.is_abandoned(now))
+        .collect();
+    Ok(open_proposals)
+}
+
+fn is_client_contract_used(
+    store: &dyn SwapStore,
+    contract_id: &ContractId,
+) -> Result<bool, anyhow::Error> {
+    let is_used = store
+        .list()?
+        .iter()
+        .any(|(_, state)| state.client_contract_id.as_ref() == Some(contract_id));
+    Ok(is_used)
+}
+
+// the transaction was sent, so we cannot know yet if our funds were locked
+fn is_unconfirmed(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<HtlcError>()
+        .map_or(false, HtlcError::is_unconfirmed)
+}
+
+fn client_contract_id(swap_state: &SwapState) -> Result<&ContractId, anyhow::Error> {
+    swap_state
+        .client_contract_id
+        .as_ref()
+        .ok_or_else(|| anyhow!("The client has not locked their funds yet"))
+}
+
+fn our_contract_id(swap_state: &SwapState) -> Result<&ContractId, anyhow::Error> {
+    swap_state
+        .our_contract_id
+        .as_ref()
+        .ok_or_else(|| anyhow!("We have not locked our funds yet"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timelock_policy::TimelockConfig;
+
+    const CLIENT_SENDER: &str = "0x70997970c51812dc3a010c7d01b50e0d17dc79c8";
+    const OUR_ADDRESS: &str = "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266";
+    const CHAIN_TIME: u64 = 1_000_000;
+    // enough for the client window with the default config
+    const CLIENT_TIMELOCK: u64 = CHAIN_TIME + 2 * 3600;
+
+    fn proposal() -> Proposal {
+        Proposal {
+            client_address: "client".to_string(),
+            client_sender_address: "0x70997970C51812dc3A010C7d01b50e0d17dc79C8".to_string(),
+            hashlock: [1u8; 32],
+            hash_function: HashFunction::Sha256,
+            quote_id: None,
+            position: Position {
+                provided_token: "eth.wei".to_string(),
+                provided_token_balance: 100,
+                requested_token: "tari".to_string(),
+                requested_token_balance: 10,
+            },
+        }
+    }
+
+    fn contract() -> ContractState {
+        ContractState {
+            sender: CLIENT_SENDER.to_string(),
+            receiver: OUR_ADDRESS.to_string(),
+            amount: 100,
+            confidential: false,
+            hashlock: [1u8; 32],
+            timelock: CLIENT_TIMELOCK,
+            status: ContractStatus::Locked,
+            preimage: None,
+        }
+    }
+
+    fn check(contract: &ContractState) -> Result<Duration, anyhow::Error> {
+        let policy = TimelockPolicy::new(TimelockConfig::default());
+        check_client_contract(
+            contract,
+            &proposal(),
+            OUR_ADDRESS,
+            &policy,
+            Network::Ethereum,
+            CHAIN_TIME,
+        )
+    }
+
+    fn assert_rejected(contract: ContractState, reason: &str) {
+        let err = check(&contract).unwrap_err();
+        assert!(err.to_string().contains(reason), "{}", err);
+    }
+
+    #[test]
+    fn it_accepts_the_expected_contract() {
+        let window = check(&contract()).unwrap();
+        assert_eq!(window, Duration::from_secs(CLIENT_TIMELOCK - CHAIN_TIME));
+    }
+
+    #[test]
+    fn it_rejects_a_wrong_sender() {
+        let mut contract = contract();
+        contract.sender = OUR_ADDRESS.to_string();
+        assert_rejected(contract, "Invalid sender");
+    }
+
+    #[test]
+    fn it_rejects_a_wrong_receiver() {
+        let mut contract = contract();
+        contract.receiver = CLIENT_SENDER.to_string();
+        assert_rejected(contract, "Invalid receiver");
+    }
+
+    #[test]
+    fn it_rejects_a_wrong_amount() {
+        let mut contract = contract();
+        contract.amount = 99;
+        assert_rejected(contract, "Invalid amount");
+    }
+
+    #[test]
+    fn it_does_not_check_the_revealed_amount_of_confidential_funds() {
+        let mut contract = contract();
+        contract.amount = 0;
+        contract.confidential = true;
+        check(&contract).unwrap();
+    }
+
+    #[test]
+    fn it_rejects_a_wrong_hashlock() {
+        let mut contract = contract();
+        contract.hashlock = [2u8; 32];
+        assert_rejected(contract, "Invalid hashlock");
+    }
+
+    #[test]
+    fn it_rejects_a_timelock_that_expires_too_soon() {
+        let mut contract = contract();
+        contract.timelock = CHAIN_TIME + 60;
+        assert_rejected(contract, "Invalid timelock");
+    }
+
+    #[test]
+    fn it_rejects_the_contracts_that_are_no_longer_locked() {
+        let mut contract = contract();
+        contract.status = ContractStatus::Refunded;
+        assert_rejected(contract, "no longer locked");
     }
 }
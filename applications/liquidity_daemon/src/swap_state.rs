@@ -0,0 +1,571 @@
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::swap_manager::{ContractId, Preimage, Proposal};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SwapStatus {
+    // the client accepted our position, but did not lock any funds yet
+    Proposed,
+    // we verified the contract of the client, but did not lock our funds yet
+    ClientLocked,
+    // both parties locked their funds, waiting for the client to reveal the preimage
+    ProviderLocked,
+    // we know the preimage, but did not withdraw the client's funds yet
+    PreimageRevealed,
+    Completed,
+    // the client never revealed the preimage and we are getting our funds back
+    Refunding,
+    Refunded,
+    // the client never locked their funds, or our lock was never confirmed before its timelock
+    Expired,
+    // the swap was aborted before we locked our funds
+    Failed,
+}
+
+impl SwapStatus {
+    pub fn is_terminal(self) -> bool {
+        matches!(
+            self,
+            SwapStatus::Completed | SwapStatus::Refunded | SwapStatus::Expired | SwapStatus::Failed
+        )
+    }
+
+    // our funds are locked in a contract, so they cannot be used in other swaps
+    pub fn has_provider_funds_locked(self) -> bool {
+        matches!(
+            self,
+            SwapStatus::ProviderLocked | SwapStatus::PreimageRevealed | SwapStatus::Refunding
+        )
+    }
+
+    // once our funds are locked the swap can only end by withdrawing the client's funds or refunding ours
+    pub fn can_transition_to(self, next: SwapStatus) -> bool {
+        use SwapStatus::*;
+        matches!(
+            (self, next),
+            (Proposed, ClientLocked)
+                | (Proposed, Expired)
+                | (Proposed, Failed)
+                | (ClientLocked, ProviderLocked)
+                | (ClientLocked, Expired)
+                | (ClientLocked, Failed)
+                | (ProviderLocked, PreimageRevealed)
+                | (ProviderLocked, Refunding)
+                | (PreimageRevealed, Completed)
+                | (Refunding, Refunded)
+        )
+    }
+}
+
+// What the swap is waiting for, so clients know how to continue it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NextAction {
+    // lock the funds and call "request_lock_funds" before the deadline
+    ClientLock,
+    ProviderLock,
+    // withdraw from our contract, revealing the preimage
+    ClientWithdraw,
+    ProviderWithdraw,
+    ProviderRefund,
+    // the client can only get their funds back after the timelock of their contract
+    ClientRefund,
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SwapTransition {
+    pub status: SwapStatus,
+    // unix timestamp (in seconds) of the transition
+    pub timestamp: u64,
+    pub reason: Option<String>,
+}
+
+// The whole lifecycle of a swap, along with the information gathered in each step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapState {
+    pub proposal: Proposal,
+    // unix timestamp (in seconds) until which the client can lock their funds
+    pub lock_deadline: u64,
+    pub client_contract_id: Option<ContractId>,
+    pub our_contract_id: Option<ContractId>,
+    // in the chain time units of the network of each contract
+    pub client_timelock: Option<u64>,
+    // ours is recorded before locking our funds, so we can always find our contract
+    pub our_timelock: Option<u64>,
+    // why we do not know if our funds were locked, until the supervisor finds it out on-chain
+    #[serde(default)]
+    pub unconfirmed_lock: Option<String>,
+    pub preimage: Option<Preimage>,
+    // the first transition is always the proposal
+    pub transitions: Vec<SwapTransition>,
+}
+
+impl SwapState {
+    pub fn new(proposal: Proposal, lock_deadline: u64) -> Self {
+        Self {
+            proposal,
+            lock_deadline,
+            client_contract_id: None,
+            our_contract_id: None,
+            client_timelock: None,
+            our_timelock: None,
+            unconfirmed_lock: None,
+            preimage: None,
+            transitions: vec![SwapTransition {
+                status: SwapStatus::Proposed,
+                timestamp: unix_timestamp(),
+                reason: None,
+            }],
+        }
+    }
+
+    pub fn status(&self) -> SwapStatus {
+        self.last_transition().status
+    }
+
+    pub fn last_transition(&self) -> &SwapTransition {
+        self.transitions
+            .last()
+            .expect("A swap always has at least one transition")
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        self.status().is_terminal()
+    }
+
+    // unix timestamp (in seconds) of the proposal
+    pub fn created_at(&self) -> u64 {
+        self.transitions[0].timestamp
+    }
+
+    // the client did not lock their funds in time, so the proposal must be expired
+    pub fn is_abandoned(&self, now: u64) -> bool {
+        self.status() == SwapStatus::Proposed && now >= self.lock_deadline
+    }
+
+    pub fn next_action(&self) -> NextAction {
+        match self.status() {
+            SwapStatus::Proposed => NextAction::ClientLock,
+            SwapStatus::ClientLocked => NextAction::ProviderLock,
+            SwapStatus::ProviderLocked => NextAction::ClientWithdraw,
+            SwapStatus::PreimageRevealed => NextAction::ProviderWithdraw,
+            SwapStatus::Refunding => NextAction::ProviderRefund,
+            SwapStatus::Refunded | SwapStatus::Expired | SwapStatus::Failed
+                if self.client_contract_id.is_some() =>
+            {
+                NextAction::ClientRefund
+            }
+            _ => NextAction::None,
+        }
+    }
+
+    pub fn client_locked(
+        &mut self,
+        contract_id: ContractId,
+        timelock: u64,
+    ) -> Result<(), SwapStateError> {
+        self.transition(SwapStatus::ClientLocked, None)?;
+        self.client_contract_id = Some(contract_id);
+        self.client_timelock = Some(timelock);
+        Ok(())
+    }
+
+    pub fn provider_locked(
+        &mut self,
+        contract_id: ContractId,
+        timelock: u64,
+    ) -> Result<(), SwapStateError> {
+        self.transition(SwapStatus::ProviderLocked, None)?;
+        self.our_contract_id = Some(contract_id);
+        self.our_timelock = Some(timelock);
+        self.unconfirmed_lock = None;
+        Ok(())
+    }
+
+    // the swap stays as it is, as our funds may be locked anyway
+    pub fn lock_unconfirmed(&mut self, reason: String) -> Result<(), SwapStateError> {
+        let current = self.status();
+        if current != SwapStatus::ClientLocked {
+            return Err(SwapStateError::InvalidTransition {
+                from: current,
+                to: SwapStatus::ProviderLocked,
+            });
+        }
+        self.unconfirmed_lock = Some(reason);
+        Ok(())
+    }
+
+    pub fn preimage_revealed(&mut self, preimage: Preimage) -> Result<(), SwapStateError> {
+        self.transition(SwapStatus::PreimageRevealed, None)?;
+        self.preimage = Some(preimage);
+        Ok(())
+    }
+
+    pub fn complete(&mut self) -> Result<(), SwapStateError> {
+        self.transition(SwapStatus::Completed, None)
+    }
+
+    pub fn start_refund(&mut self, reason: String) -> Result<(), SwapStateError> {
+        self.transition(SwapStatus::Refunding, Some(reason))
+    }
+
+    pub fn refunded(&mut self) -> Result<(), SwapStateError> {
+        self.transition(SwapStatus::Refunded, None)
+    }
+
+    pub fn expire(&mut self, reason: String) -> Result<(), SwapStateError> {
+        self.transition(SwapStatus::Expired, Some(reason))
+    }
+
+    pub fn fail(&mut self, reason: String) -> Result<(), SwapStateError> {
+        self.transition(SwapStatus::Failed, Some(reason))
+    }
+
+    fn transition(
+        &mut self,
+        status: SwapStatus,
+        reason: Option<String>,
+    ) -> Result<(), SwapStateError> {
+        let current = self.status();
+        if !current.can_transition_to(status) {
+            return Err(SwapStateError::InvalidTransition {
+                from: current,
+                to: status,
+            });
+        }
+        self.transitions.push(SwapTransition {
+            status,
+            timestamp: unix_timestamp(),
+            reason,
+        });
+        Ok(())
+    }
+}
+
+// Criteria to search for swaps, all of them are optional
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SwapFilter {
+    pub statuses: Option<Vec<SwapStatus>>,
+    // unix timestamps (in seconds) of the proposals, both inclusive
+    pub created_after: Option<u64>,
+    pub created_before: Option<u64>,
+}
+
+impl SwapFilter {
+    pub fn matches(&self, swap_state: &SwapState) -> bool {
+        let created_at = swap_state.created_at();
+        self.statuses
+            .as_ref()
+            .map_or(true, |statuses| statuses.contains(&swap_state.status()))
+            && self.created_after.map_or(true, |after| created_at >= after)
+            && self
+                .created_before
+                .map_or(true, |before| created_at <= before)
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SwapStateError {
+    #[error("Invalid swap transition from {from:?} to {to:?}")]
+    InvalidTransition { from: SwapStatus, to: SwapStatus },
+}
+
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swap_manager::{HashFunction, Position};
+
+    const ALL_STATUSES: [SwapStatus; 9] = [
+        SwapStatus::Proposed,
+        SwapStatus::ClientLocked,
+        SwapStatus::ProviderLocked,
+        SwapStatus::PreimageRevealed,
+        SwapStatus::Completed,
+        SwapStatus::Refunding,
+        SwapStatus::Refunded,
+        SwapStatus::Expired,
+        SwapStatus::Failed,
+    ];
+
+    fn new_swap() -> SwapState {
+        new_swap_with_deadline(u64::MAX)
+    }
+
+    fn new_swap_with_deadline(lock_deadline: u64) -> SwapState {
+        SwapState::new(
+            Proposal {
+                client_address: "client".to_string(),
+                client_sender_address: "client_sender".to_string(),
+                hashlock: [0u8; 32],
+                hash_function: HashFunction::Sha256,
+                quote_id: None,
+                position: Position {
+                    provided_token: "eth.wei".to_string(),
+                    provided_token_balance: 100,
+                    requested_token: "tari".to_string(),
+                    requested_token_balance: 10,
+                },
+            },
+            lock_deadline,
+        )
+    }
+
+    fn locked_swap() -> SwapState {
+        let mut swap = new_swap();
+        swap.client_locked("client_contract".to_string(), 200)
+            .unwrap();
+        swap.provider_locked("our_contract".to_string(), 100)
+            .unwrap();
+        swap
+    }
+
+    #[test]
+    fn it_completes_a_swap() {
+        let mut swap = locked_swap();
+        swap.preimage_revealed([1u8; 32]).unwrap();
+        swap.complete().unwrap();
+
+        assert_eq!(swap.status(), SwapStatus::Completed);
+        assert!(swap.is_terminal());
+        assert_eq!(swap.client_contract_id.as_deref(), Some("client_contract"));
+        assert_eq!(swap.our_contract_id.as_deref(), Some("our_contract"));
+        assert_eq!(swap.preimage, Some([1u8; 32]));
+        let statuses: Vec<SwapStatus> = swap.transitions.iter().map(|t| t.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                SwapStatus::Proposed,
+                SwapStatus::ClientLocked,
+                SwapStatus::ProviderLocked,
+                SwapStatus::PreimageRevealed,
+                SwapStatus::Completed,
+            ]
+        );
+    }
+
+    #[test]
+    fn it_refunds_a_swap_with_a_reason() {
+        let mut swap = locked_swap();
+        swap.start_refund("timelock expired".to_string()).unwrap();
+        assert!(swap.status().has_provider_funds_locked());
+        swap.refunded().unwrap();
+
+        assert_eq!(swap.status(), SwapStatus::Refunded);
+        let refunding = &swap.transitions[swap.transitions.len() - 2];
+        assert_eq!(refunding.status, SwapStatus::Refunding);
+        assert_eq!(refunding.reason.as_deref(), Some("timelock expired"));
+    }
+
+    // applies the transition to the swap, which must reject it without changing anything
+    fn assert_rejected(
+        mut swap: SwapState,
+        to: SwapStatus,
+        transition: impl FnOnce(&mut SwapState) -> Result<(), SwapStateError>,
+    ) {
+        let from = swap.status();
+        let transitions = swap.transitions.clone();
+
+        let err = transition(&mut swap).unwrap_err();
+        assert_eq!(err, SwapStateError::InvalidTransition { from, to });
+        assert_eq!(swap.status(), from);
+        assert_eq!(swap.transitions, transitions);
+    }
+
+    #[test]
+    fn it_rejects_illegal_transitions() {
+        let mut completed = locked_swap();
+        completed.preimage_revealed([1u8; 32]).unwrap();
+        completed.complete().unwrap();
+        assert_rejected(completed, SwapStatus::Refunding, |swap| {
+            swap.start_refund("timelock expired".to_string())
+        });
+
+        let mut expired = new_swap();
+        expired.expire("abandoned".to_string()).unwrap();
+        assert_rejected(expired, SwapStatus::ClientLocked, |swap| {
+            swap.client_locked("client_contract".to_string(), 200)
+        });
+
+        let mut refunded = locked_swap();
+        refunded
+            .start_refund("timelock expired".to_string())
+            .unwrap();
+        refunded.refunded().unwrap();
+        assert_rejected(refunded, SwapStatus::Completed, |swap| swap.complete());
+
+        assert_rejected(new_swap(), SwapStatus::PreimageRevealed, |swap| {
+            swap.preimage_revealed([1u8; 32])
+        });
+
+        // our funds are locked, so only a refund can end the swap without the preimage
+        assert_rejected(locked_swap(), SwapStatus::Expired, |swap| {
+            swap.expire("timelock expired".to_string())
+        });
+    }
+
+    #[test]
+    fn terminal_states_have_no_transitions() {
+        for from in ALL_STATUSES.into_iter().filter(|s| s.is_terminal()) {
+            assert!(ALL_STATUSES.iter().all(|to| !from.can_transition_to(*to)));
+        }
+    }
+
+    #[test]
+    fn it_expires_a_swap_whose_lock_was_never_confirmed() {
+        let mut swap = new_swap();
+        swap.client_locked("client_contract".to_string(), 200)
+            .unwrap();
+        swap.lock_unconfirmed("transaction dropped".to_string())
+            .unwrap();
+        assert_eq!(swap.status(), SwapStatus::ClientLocked);
+        assert_eq!(
+            swap.unconfirmed_lock.as_deref(),
+            Some("transaction dropped")
+        );
+
+        swap.expire("our funds were never locked".to_string())
+            .unwrap();
+        assert!(swap.is_terminal());
+        // the client still has to get their own funds back
+        assert_eq!(swap.next_action(), NextAction::ClientRefund);
+    }
+
+    #[test]
+    fn it_confirms_an_unconfirmed_lock() {
+        let mut swap = new_swap();
+        assert!(swap.lock_unconfirmed("timeout".to_string()).is_err());
+
+        swap.client_locked("client_contract".to_string(), 200)
+            .unwrap();
+        swap.lock_unconfirmed("timeout".to_string()).unwrap();
+        swap.provider_locked("our_contract".to_string(), 100)
+            .unwrap();
+        assert_eq!(swap.unconfirmed_lock, None);
+        assert!(swap.lock_unconfirmed("timeout".to_string()).is_err());
+    }
+
+    #[test]
+    fn it_does_not_complete_a_swap_without_the_preimage() {
+        let mut swap = locked_swap();
+        let err = swap.complete().unwrap_err();
+        assert_eq!(
+            err,
+            SwapStateError::InvalidTransition {
+                from: SwapStatus::ProviderLocked,
+                to: SwapStatus::Completed,
+            }
+        );
+        // a rejected transition does not change the swap
+        assert_eq!(swap.status(), SwapStatus::ProviderLocked);
+        assert_eq!(swap.transitions.len(), 3);
+    }
+
+    #[test]
+    fn it_does_not_fail_a_swap_once_our_funds_are_locked() {
+        let mut swap = locked_swap();
+        assert!(swap.fail("unexpected error".to_string()).is_err());
+        assert!(swap.expire("too late".to_string()).is_err());
+    }
+
+    #[test]
+    fn it_does_not_lock_our_funds_before_the_client() {
+        let mut swap = new_swap();
+        assert!(swap
+            .provider_locked("our_contract".to_string(), 100)
+            .is_err());
+        assert_eq!(swap.our_contract_id, None);
+    }
+
+    #[test]
+    fn it_does_not_refund_a_completed_swap() {
+        let mut swap = locked_swap();
+        swap.preimage_revealed([1u8; 32]).unwrap();
+        assert!(swap.start_refund("timelock expired".to_string()).is_err());
+        swap.complete().unwrap();
+        assert!(swap.start_refund("timelock expired".to_string()).is_err());
+        assert!(swap.refunded().is_err());
+    }
+
+    #[test]
+    fn it_does_not_reuse_an_expired_proposal() {
+        let mut swap = new_swap();
+        swap.expire("the client never locked the funds".to_string())
+            .unwrap();
+        assert!(swap
+            .client_locked("client_contract".to_string(), 200)
+            .is_err());
+        assert_eq!(
+            swap.last_transition().reason.as_deref(),
+            Some("the client never locked the funds")
+        );
+    }
+
+    #[test]
+    fn it_reports_the_next_action() {
+        let mut swap = new_swap();
+        assert_eq!(swap.next_action(), NextAction::ClientLock);
+        swap.client_locked("client_contract".to_string(), 200)
+            .unwrap();
+        assert_eq!(swap.next_action(), NextAction::ProviderLock);
+        swap.provider_locked("our_contract".to_string(), 100)
+            .unwrap();
+        assert_eq!(swap.next_action(), NextAction::ClientWithdraw);
+        swap.start_refund("timelock expired".to_string()).unwrap();
+        assert_eq!(swap.next_action(), NextAction::ProviderRefund);
+        swap.refunded().unwrap();
+        // the client still has to get their own funds back
+        assert_eq!(swap.next_action(), NextAction::ClientRefund);
+
+        let mut swap = new_swap();
+        swap.expire("the client never locked the funds".to_string())
+            .unwrap();
+        assert_eq!(swap.next_action(), NextAction::None);
+    }
+
+    #[test]
+    fn it_filters_swaps() {
+        let swap = locked_swap();
+        let created_at = swap.created_at();
+        assert!(SwapFilter::default().matches(&swap));
+
+        let by_status = |statuses: Vec<SwapStatus>| SwapFilter {
+            statuses: Some(statuses),
+            ..Default::default()
+        };
+        assert!(by_status(vec![SwapStatus::Proposed, SwapStatus::ProviderLocked]).matches(&swap));
+        assert!(!by_status(vec![SwapStatus::Completed]).matches(&swap));
+
+        let by_time = |created_after, created_before| SwapFilter {
+            statuses: None,
+            created_after,
+            created_before,
+        };
+        assert!(by_time(Some(created_at), Some(created_at)).matches(&swap));
+        assert!(!by_time(Some(created_at + 1), None).matches(&swap));
+        assert!(!by_time(None, Some(created_at - 1)).matches(&swap));
+    }
+
+    #[test]
+    fn it_abandons_the_proposals_after_the_deadline() {
+        let swap = new_swap_with_deadline(100);
+        assert!(!swap.is_abandoned(99));
+        assert!(swap.is_abandoned(100));
+
+        // the deadline no longer applies once the client locked their funds
+        let mut swap = new_swap_with_deadline(100);
+        swap.client_locked("client_contract".to_string(), 200)
+            .unwrap();
+        assert!(!swap.is_abandoned(100));
+    }
+}
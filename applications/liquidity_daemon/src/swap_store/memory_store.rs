@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use super::{SwapStore, SwapStoreError};
+use crate::swap_manager::SwapId;
+use crate::swap_state::SwapState;
+
+// Volatile store, all the swap information is lost when the daemon stops
+#[derive(Default)]
+pub struct MemorySwapStore {
+    swaps: RwLock<HashMap<SwapId, SwapState>>,
+}
+
+impl MemorySwapStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SwapStore for MemorySwapStore {
+    fn get(&self, swap_id: &SwapId) -> Result<Option<SwapState>, SwapStoreError> {
+        let swaps = self.swaps.read().unwrap();
+        Ok(swaps.get(swap_id).cloned())
+    }
+
+    fn save(&self, swap_id: &SwapId, state: &SwapState) -> Result<(), SwapStoreError> {
+        let mut swaps = self.swaps.write().unwrap();
+        swaps.insert(*swap_id, state.clone());
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<(SwapId, SwapState)>, SwapStoreError> {
+        let swaps = self.swaps.read().unwrap();
+        let entries = swaps
+            .iter()
+            .map(|(swap_id, state)| (*swap_id, state.clone()))
+            .collect();
+        Ok(entries)
+    }
+
+    fn remove(&self, swap_id: &SwapId) -> Result<(), SwapStoreError> {
+        let mut swaps = self.swaps.write().unwrap();
+        swaps.remove(swap_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+mod memory_store;
+pub use memory_store::MemorySwapStore;
+
+mod sled_store;
+pub use sled_store::SledSwapStore;
+
+use thiserror::Error;
+
+use crate::swap_manager::SwapId;
+use crate::swap_state::SwapState;
+
+/// Persistence layer for the state of every swap handled by the daemon
+pub trait SwapStore: Send + Sync {
+    fn get(&self, swap_id: &SwapId) -> Result<Option<SwapState>, SwapStoreError>;
+
+    // Each call must atomically replace any previous state of the swap
+    fn save(&self, swap_id: &SwapId, state: &SwapState) -> Result<(), SwapStoreError>;
+
+    fn list(&self) -> Result<Vec<(SwapId, SwapState)>, SwapStoreError>;
+
+    // Only for the swaps that never had any funds locked, the rest must be kept as a record
+    fn remove(&self, swap_id: &SwapId) -> Result<(), SwapStoreError>;
+
+    fn list_ongoing(&self) -> Result<Vec<(SwapId, SwapState)>, SwapStoreError> {
+        let ongoing = self
+            .list()?
+            .into_iter()
+            .filter(|(_, state)| !state.is_terminal())
+            .collect();
+        Ok(ongoing)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SwapStoreError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sled::Error),
+    #[error("Invalid swap state encoding: {0}")]
+    EncodingError(#[from] serde_json::Error),
+    #[error("Invalid swap id in the database: {0}")]
+    InvalidSwapId(#[from] uuid::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swap_manager::{HashFunction, Position, Proposal};
+    use uuid::Uuid;
+
+    fn new_swap() -> SwapState {
+        SwapState::new(
+            Proposal {
+                client_address: "client".to_string(),
+                client_sender_address: "client_sender".to_string(),
+                hashlock: [0u8; 32],
+                hash_function: HashFunction::Sha256,
+                quote_id: None,
+                position: Position {
+                    provided_token: "eth.wei".to_string(),
+                    provided_token_balance: 100,
+                    requested_token: "tari".to_string(),
+                    requested_token_balance: 10,
+                },
+            },
+            u64::MAX,
+        )
+    }
+
+    fn completed_swap() -> SwapState {
+        let mut swap = new_swap();
+        swap.client_locked("client_contract".to_string(), 200)
+            .unwrap();
+        swap.provider_locked("our_contract".to_string(), 100)
+            .unwrap();
+        swap.preimage_revealed([1u8; 32]).unwrap();
+        swap.complete().unwrap();
+        swap
+    }
+
+    // the states do not implement "PartialEq", so we compare their encodings
+    fn assert_same_state(found: Option<SwapState>, expected: &SwapState) {
+        let found = serde_json::to_value(found.expect("swap not found")).unwrap();
+        assert_eq!(found, serde_json::to_value(expected).unwrap());
+    }
+
+    fn ids(mut swaps: Vec<(SwapId, SwapState)>) -> Vec<SwapId> {
+        swaps.sort_by_key(|(swap_id, _)| *swap_id);
+        swaps.into_iter().map(|(swap_id, _)| swap_id).collect()
+    }
+
+    fn it_round_trips_the_swaps(store: &dyn SwapStore) {
+        let ongoing_id = Uuid::new_v4();
+        let mut ongoing = new_swap();
+        store.save(&ongoing_id, &ongoing).unwrap();
+        let completed_id = Uuid::new_v4();
+        let completed = completed_swap();
+        store.save(&completed_id, &completed).unwrap();
+
+        assert_same_state(store.get(&ongoing_id).unwrap(), &ongoing);
+        assert_same_state(store.get(&completed_id).unwrap(), &completed);
+        assert!(store.get(&Uuid::new_v4()).unwrap().is_none());
+
+        // each save replaces the previous state
+        ongoing
+            .client_locked("client_contract".to_string(), 200)
+            .unwrap();
+        store.save(&ongoing_id, &ongoing).unwrap();
+        assert_same_state(store.get(&ongoing_id).unwrap(), &ongoing);
+
+        let mut all_ids = vec![ongoing_id, completed_id];
+        all_ids.sort();
+        assert_eq!(ids(store.list().unwrap()), all_ids);
+        assert_eq!(ids(store.list_ongoing().unwrap()), vec![ongoing_id]);
+
+        store.remove(&completed_id).unwrap();
+        assert!(store.get(&completed_id).unwrap().is_none());
+        assert_eq!(ids(store.list().unwrap()), vec![ongoing_id]);
+    }
+
+    #[test]
+    fn the_memory_store_round_trips_the_swaps() {
+        it_round_trips_the_swaps(&MemorySwapStore::new());
+    }
+
+    #[test]
+    fn the_sled_store_round_trips_the_swaps() {
+        let path = std::env::temp_dir().join(format!("swap_store_{}", Uuid::new_v4()));
+        it_round_trips_the_swaps(&SledSwapStore::open(&path).unwrap());
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn the_sled_store_keeps_the_swaps_after_reopening() {
+        let path = std::env::temp_dir().join(format!("swap_store_{}", Uuid::new_v4()));
+        let swap_id = Uuid::new_v4();
+        let mut swap = new_swap();
+        swap.client_locked("client_contract".to_string(), 200)
+            .unwrap();
+        swap.lock_unconfirmed("timeout".to_string()).unwrap();
+        {
+            let store = SledSwapStore::open(&path).unwrap();
+            store.save(&swap_id, &swap).unwrap();
+            store.save(&Uuid::new_v4(), &completed_swap()).unwrap();
+        }
+
+        let store = SledSwapStore::open(&path).unwrap();
+        let ongoing = store.list_ongoing().unwrap();
+        assert_eq!(ids(ongoing.clone()), vec![swap_id]);
+        assert_same_state(ongoing.into_iter().next().map(|(_, state)| state), &swap);
+        assert_eq!(store.list().unwrap().len(), 2);
+
+        drop(store);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -0,0 +1,52 @@
+use std::path::Path;
+
+use super::{SwapStore, SwapStoreError};
+use crate::swap_manager::SwapId;
+use crate::swap_state::SwapState;
+
+// Embedded on-disk store, the swap states are encoded as JSON and indexed by the swap id bytes
+pub struct SledSwapStore {
+    db: sled::Db,
+}
+
+impl SledSwapStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, SwapStoreError> {
+        let db = sled::open(path)?;
+        Ok(Self { db })
+    }
+}
+
+impl SwapStore for SledSwapStore {
+    fn get(&self, swap_id: &SwapId) -> Result<Option<SwapState>, SwapStoreError> {
+        match self.db.get(swap_id.as_bytes())? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save(&self, swap_id: &SwapId, state: &SwapState) -> Result<(), SwapStoreError> {
+        let value = serde_json::to_vec(state)?;
+        self.db.insert(swap_id.as_bytes(), value)?;
+        // we must not acknowledge a transition until it's durable, as it may involve locked funds
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<(SwapId, SwapState)>, SwapStoreError> {
+        self.db
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry?;
+                let swap_id = SwapId::from_slice(&key)?;
+                let state = serde_json::from_slice(&value)?;
+                Ok((swap_id, state))
+            })
+            .collect()
+    }
+
+    fn remove(&self, swap_id: &SwapId) -> Result<(), SwapStoreError> {
+        self.db.remove(swap_id.as_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+}
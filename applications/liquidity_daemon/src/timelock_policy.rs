@@ -0,0 +1,327 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, ensure};
+use serde::{Deserialize, Serialize};
+
+use crate::token::Network;
+
+// All the durations are in seconds, the policy converts them to the chain time units of each network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimelockConfig {
+    // minimum time left in the client's timelock when they ask us to lock our funds
+    pub ethereum_min_client_timelock_secs: u64,
+    pub tari_min_client_timelock_secs: u64,
+    // time between the expiration of our contract and the client's one,
+    // so we can still withdraw from the client's contract after they reveal the preimage at the last moment
+    pub safety_margin_secs: u64,
+    // minimum time that the client has to withdraw from our contract
+    pub min_provider_timelock_secs: u64,
+    // Tari timelocks are in epochs, so we need an estimation of their duration
+    pub tari_epoch_duration_secs: u64,
+    // the epochs are not always of the same duration, so the client timelocks are converted with epochs
+    // shorter than the estimation by this percentage, and ours with epochs longer by the same percentage
+    pub tari_epoch_duration_variance_percent: u64,
+}
+
+impl Default for TimelockConfig {
+    fn default() -> Self {
+        Self {
+            ethereum_min_client_timelock_secs: 3600,
+            tari_min_client_timelock_secs: 3600,
+            safety_margin_secs: 1800,
+            min_provider_timelock_secs: 600,
+            tari_epoch_duration_secs: 60,
+            tari_epoch_duration_variance_percent: 20,
+        }
+    }
+}
+
+impl TimelockConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.tari_epoch_duration_secs == 0 {
+            return Err("tari_epoch_duration_secs must be greater than zero".to_string());
+        }
+        if self.tari_epoch_duration_variance_percent >= 100 {
+            return Err("tari_epoch_duration_variance_percent must be lower than 100".to_string());
+        }
+        // otherwise any client contract accepted by the minimums would be rejected by the margin
+        for min_client_timelock in [
+            self.ethereum_min_client_timelock_secs,
+            self.tari_min_client_timelock_secs,
+        ] {
+            if min_client_timelock < self.safety_margin_secs + self.min_provider_timelock_secs {
+                return Err(
+                    "the minimum client timelocks must cover the safety margin and the minimum provider timelock"
+                        .to_string(),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+// The provider must always be able to refund before the client, so the client can never get both assets
+#[derive(Debug, Clone)]
+pub struct TimelockPolicy {
+    config: TimelockConfig,
+}
+
+impl TimelockPolicy {
+    pub fn new(config: TimelockConfig) -> Self {
+        Self { config }
+    }
+
+    // time left until the client can refund, rejecting the contracts that expire too soon
+    pub fn client_window(
+        &self,
+        network: Network,
+        client_timelock: u64,
+        chain_time: u64,
+    ) -> Result<Duration, anyhow::Error> {
+        let remaining = client_timelock.saturating_sub(chain_time);
+        let window = self.to_duration(network, remaining)?;
+
+        let min_window = Duration::from_secs(self.min_client_timelock_secs(network));
+        ensure!(
+            window >= min_window,
+            "Invalid timelock, the client contract expires in {}s but at least {}s are required",
+            window.as_secs(),
+            min_window.as_secs()
+        );
+
+        Ok(window)
+    }
+
+    // our refund window is always shorter than the client's one by the safety margin
+    pub fn provider_window(&self, client_window: Duration) -> Result<Duration, anyhow::Error> {
+        let safety_margin = Duration::from_secs(self.config.safety_margin_secs);
+        let min_window = Duration::from_secs(self.config.min_provider_timelock_secs);
+        let window = client_window.saturating_sub(safety_margin);
+        ensure!(
+            window >= min_window,
+            "Insufficient timelock margin, the client contract expires in {}s but we need {}s of margin and {}s to lock our funds",
+            client_window.as_secs(),
+            safety_margin.as_secs(),
+            min_window.as_secs()
+        );
+        Ok(window)
+    }
+
+    // the absolute timelock for our contract, rounded down so it never exceeds the window
+    pub fn provider_timelock(
+        &self,
+        network: Network,
+        chain_time: u64,
+        window: Duration,
+    ) -> Result<u64, anyhow::Error> {
+        let units = self.to_chain_units(network, window);
+        ensure!(
+            units > 0,
+            "The timelock window of {}s is shorter than a single {:?} time unit",
+            window.as_secs(),
+            network
+        );
+        chain_time
+            .checked_add(units)
+            .ok_or_else(|| anyhow!("Invalid timelock for the chain time {}", chain_time))
+    }
+
+    fn min_client_timelock_secs(&self, network: Network) -> u64 {
+        match network {
+            Network::Ethereum => self.config.ethereum_min_client_timelock_secs,
+            Network::Tari => self.config.tari_min_client_timelock_secs,
+        }
+    }
+
+    // timelocks are in the units of the chain time of each network (seconds in Ethereum, epochs in Tari)
+    // the durations never overestimate the time left until the client can refund
+    fn to_duration(&self, network: Network, units: u64) -> Result<Duration, anyhow::Error> {
+        match network {
+            Network::Ethereum => Ok(Duration::from_secs(units)),
+            Network::Tari => u32::try_from(units)
+                .ok()
+                .and_then(|epochs| self.shortest_epoch().checked_mul(epochs))
+                .ok_or_else(|| anyhow!("The timelock of {} epochs is too long", units)),
+        }
+    }
+
+    // and the chain units never overestimate the time until we can refund
+    fn to_chain_units(&self, network: Network, duration: Duration) -> u64 {
+        match network {
+            Network::Ethereum => duration.as_secs(),
+            Network::Tari => {
+                let epochs = duration.as_millis() / self.longest_epoch().as_millis();
+                u64::try_from(epochs).unwrap_or(u64::MAX)
+            }
+        }
+    }
+
+    fn shortest_epoch(&self) -> Duration {
+        let variance = self.config.tari_epoch_duration_variance_percent as u32;
+        Duration::from_secs(self.config.tari_epoch_duration_secs) * (100 - variance) / 100
+    }
+
+    fn longest_epoch(&self) -> Duration {
+        let variance = self.config.tari_epoch_duration_variance_percent as u32;
+        Duration::from_secs(self.config.tari_epoch_duration_secs) * (100 + variance) / 100
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHAIN_TIME: u64 = 1_000;
+
+    // without variance, so the epochs are always of the estimated duration
+    fn exact_config() -> TimelockConfig {
+        TimelockConfig {
+            tari_epoch_duration_variance_percent: 0,
+            ..TimelockConfig::default()
+        }
+    }
+
+    fn policy(config: TimelockConfig) -> TimelockPolicy {
+        config.validate().unwrap();
+        TimelockPolicy::new(config)
+    }
+
+    #[test]
+    fn it_validates_the_config() {
+        TimelockConfig::default().validate().unwrap();
+
+        let mut config = TimelockConfig::default();
+        config.tari_epoch_duration_secs = 0;
+        assert!(config.validate().is_err());
+
+        let mut config = TimelockConfig::default();
+        config.tari_epoch_duration_variance_percent = 100;
+        assert!(config.validate().is_err());
+
+        let mut config = TimelockConfig::default();
+        config.ethereum_min_client_timelock_secs = 2399;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn it_rejects_client_windows_below_the_minimum() {
+        let policy = policy(exact_config());
+
+        let window = policy
+            .client_window(Network::Ethereum, CHAIN_TIME + 3600, CHAIN_TIME)
+            .unwrap();
+        assert_eq!(window, Duration::from_secs(3600));
+        assert!(policy
+            .client_window(Network::Ethereum, CHAIN_TIME + 3599, CHAIN_TIME)
+            .is_err());
+
+        // 60 epochs of 60s
+        let window = policy
+            .client_window(Network::Tari, CHAIN_TIME + 60, CHAIN_TIME)
+            .unwrap();
+        assert_eq!(window, Duration::from_secs(3600));
+        assert!(policy
+            .client_window(Network::Tari, CHAIN_TIME + 59, CHAIN_TIME)
+            .is_err());
+    }
+
+    #[test]
+    fn it_rejects_expired_client_contracts() {
+        let policy = policy(exact_config());
+        assert!(policy
+            .client_window(Network::Ethereum, CHAIN_TIME - 1, CHAIN_TIME)
+            .is_err());
+        assert!(policy
+            .client_window(Network::Tari, CHAIN_TIME - 1, CHAIN_TIME)
+            .is_err());
+    }
+
+    #[test]
+    fn it_rejects_tari_timelocks_too_far_in_the_future() {
+        let policy = policy(exact_config());
+        assert!(policy
+            .client_window(Network::Tari, u64::MAX, CHAIN_TIME)
+            .is_err());
+    }
+
+    #[test]
+    fn it_keeps_the_safety_margin_in_our_window() {
+        let policy = policy(exact_config());
+
+        let window = policy.provider_window(Duration::from_secs(7200)).unwrap();
+        assert_eq!(window, Duration::from_secs(5400));
+
+        // the margin and the minimum provider window add up to 2400s
+        let window = policy.provider_window(Duration::from_secs(2400)).unwrap();
+        assert_eq!(window, Duration::from_secs(600));
+        assert!(policy.provider_window(Duration::from_secs(2399)).is_err());
+    }
+
+    #[test]
+    fn it_converts_our_window_to_chain_time() {
+        let policy = policy(exact_config());
+
+        let timelock = policy
+            .provider_timelock(Network::Ethereum, CHAIN_TIME, Duration::from_secs(600))
+            .unwrap();
+        assert_eq!(timelock, CHAIN_TIME + 600);
+
+        // rounded down to whole epochs
+        let timelock = policy
+            .provider_timelock(Network::Tari, CHAIN_TIME, Duration::from_secs(659))
+            .unwrap();
+        assert_eq!(timelock, CHAIN_TIME + 10);
+
+        assert!(policy
+            .provider_timelock(Network::Tari, CHAIN_TIME, Duration::from_secs(59))
+            .is_err());
+        assert!(policy
+            .provider_timelock(Network::Ethereum, u64::MAX, Duration::from_secs(600))
+            .is_err());
+    }
+
+    #[test]
+    fn it_assumes_the_worst_epoch_durations() {
+        // epochs between 48s and 72s
+        let policy = policy(TimelockConfig::default());
+
+        // the client can refund sooner if the epochs are shorter
+        let window = policy
+            .client_window(Network::Tari, CHAIN_TIME + 100, CHAIN_TIME)
+            .unwrap();
+        assert_eq!(window, Duration::from_secs(4800));
+
+        // and we could only refund later if the epochs are longer
+        let timelock = policy
+            .provider_timelock(Network::Tari, CHAIN_TIME, Duration::from_secs(7200))
+            .unwrap();
+        assert_eq!(timelock, CHAIN_TIME + 100);
+    }
+
+    #[test]
+    fn our_tari_contract_expires_first_whatever_the_epoch_duration() {
+        let config = TimelockConfig::default();
+        let policy = policy(config.clone());
+        let estimation = config.tari_epoch_duration_secs;
+        let variance = config.tari_epoch_duration_variance_percent;
+        let shortest_epoch = estimation * (100 - variance) / 100;
+        let longest_epoch = estimation * (100 + variance) / 100;
+
+        for client_epochs in [75, 100, 1000, 10_000] {
+            let client_window = policy
+                .client_window(Network::Tari, CHAIN_TIME + client_epochs, CHAIN_TIME)
+                .unwrap();
+            let our_window = policy.provider_window(client_window).unwrap();
+            let our_epochs = policy
+                .provider_timelock(Network::Tari, CHAIN_TIME, our_window)
+                .unwrap()
+                - CHAIN_TIME;
+
+            // even if the epochs of our contract are the longest and the client ones the shortest
+            let our_refund = our_epochs * longest_epoch;
+            let client_refund = client_epochs * shortest_epoch;
+            assert!(our_refund + config.safety_margin_secs <= client_refund);
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+use tari_template_lib::prelude::{NonFungibleId, ResourceAddress};
+
+pub const ETH_WEI_TOKEN: &str = "eth.wei";
+pub const TARI_TOKEN: &str = "tari";
+// the amounts are handled as u64, which cannot hold more than 19 digits
+const MAX_DECIMALS: u8 = 18;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Network {
+    Ethereum,
+    Tari,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AssetKind {
+    // the coin of the network itself (e.g. ETH in Ethereum or Tari in the Tari network)
+    Native,
+    Erc20 {
+        contract_address: String,
+    },
+    // a single NFT of the collection, so the balances of its positions can only be one
+    Erc721 {
+        contract_address: String,
+        token_id: String,
+    },
+    TariResource {
+        resource_address: String,
+    },
+    // a single token of a non-fungible resource, identified by its canonical id (e.g. "str:my_nft")
+    TariNonFungible {
+        resource_address: String,
+        token_id: String,
+    },
+    // the confidential funds of a resource, the clients can only pay with them if we know the view key
+    TariConfidential {
+        resource_address: String,
+        #[serde(default)]
+        view_key_id: Option<u64>,
+    },
+}
+
+// A token that can be swapped, identified in the positions and proposals by its id
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Token {
+    pub id: String,
+    pub network: Network,
+    pub kind: AssetKind,
+    pub decimals: u8,
+    pub symbol: String,
+}
+
+impl Token {
+    pub fn is_non_fungible(&self) -> bool {
+        matches!(
+            self.kind,
+            AssetKind::Erc721 { .. } | AssetKind::TariNonFungible { .. }
+        )
+    }
+
+    // amounts in the smallest unit of the token, displayed with its decimals and symbol (e.g. "1.5 ETH")
+    pub fn format_amount(&self, amount: u64) -> String {
+        let decimals = usize::from(self.decimals);
+        if decimals == 0 {
+            return format!("{} {}", amount, self.symbol);
+        }
+        let digits = format!("{:0>width$}", amount, width = decimals + 1);
+        let (units, fraction) = digits.split_at(digits.len() - decimals);
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.is_empty() {
+            format!("{} {}", units, self.symbol)
+        } else {
+            format!("{}.{} {}", units, fraction, self.symbol)
+        }
+    }
+
+    // so the backends of the tokens never fail to start because of a wrong config
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        match (&self.network, &self.kind) {
+            (_, AssetKind::Native) => {}
+            (Network::Ethereum, AssetKind::Erc20 { contract_address }) => {
+                parse_ethereum_address(contract_address)?;
+            }
+            (
+                Network::Ethereum,
+                AssetKind::Erc721 {
+                    contract_address,
+                    token_id,
+                },
+            ) => {
+                parse_ethereum_address(contract_address)?;
+                U256::from_dec_str(token_id)
+                    .map_err(|_| anyhow!("Invalid ERC721 token id '{}'", token_id))?;
+            }
+            (Network::Tari, AssetKind::TariResource { resource_address })
+            | (
+                Network::Tari,
+                AssetKind::TariConfidential {
+                    resource_address, ..
+                },
+            ) => {
+                parse_resource_address(resource_address)?;
+            }
+            (
+                Network::Tari,
+                AssetKind::TariNonFungible {
+                    resource_address,
+                    token_id,
+                },
+            ) => {
+                parse_resource_address(resource_address)?;
+                NonFungibleId::try_from_canonical_string(token_id)
+                    .map_err(|_| anyhow!("Invalid Tari NFT id '{}'", token_id))?;
+            }
+            (network, kind) => bail!("{:?} assets do not exist in {:?}", kind, network),
+        }
+        if self.decimals > MAX_DECIMALS {
+            bail!("Tokens cannot have more than {} decimals", MAX_DECIMALS);
+        }
+        // an NFT cannot be split
+        if self.is_non_fungible() && self.decimals != 0 {
+            bail!("Non-fungible tokens cannot have decimals");
+        }
+        Ok(())
+    }
+}
+
+fn parse_ethereum_address(input: &str) -> Result<Address, anyhow::Error> {
+    Address::from_str(input).map_err(|_| anyhow!("Invalid Ethereum address '{}'", input))
+}
+
+fn parse_resource_address(input: &str) -> Result<ResourceAddress, anyhow::Error> {
+    ResourceAddress::from_str(input)
+        .map_err(|_| anyhow!("Invalid Tari resource address '{}'", input))
+}
+
+#[derive(Debug, Clone)]
+pub struct TokenRegistry {
+    tokens: HashMap<String, Token>,
+}
+
+impl TokenRegistry {
+    // the native tokens of all networks are always available, with any extra tokens defined by the user
+    pub fn new(extra_tokens: Vec<Token>) -> Result<Self, anyhow::Error> {
+        let mut tokens: HashMap<String, Token> = builtin_tokens()
+            .into_iter()
+            .map(|t| (t.id.clone(), t))
+            .collect();
+
+        for token in extra_tokens {
+            if tokens.contains_key(&token.id) {
+                return Err(anyhow!("Token '{}' is defined more than once", token.id));
+            }
+            token
+                .validate()
+                .map_err(|e| anyhow!("Invalid token '{}': {}", token.id, e))?;
+            tokens.insert(token.id.clone(), token);
+        }
+
+        Ok(Self { tokens })
+    }
+
+    pub fn get(&self, id: &str) -> Result<&Token, anyhow::Error> {
+        self.tokens
+            .get(id)
+            .ok_or_else(|| anyhow!("Unknown token '{}'", id))
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = &Token> {
+        self.tokens.values()
+    }
+}
+
+fn builtin_tokens() -> Vec<Token> {
+    vec![
+        Token {
+            id: ETH_WEI_TOKEN.to_string(),
+            network: Network::Ethereum,
+            kind: AssetKind::Native,
+            decimals: 18,
+            symbol: "ETH".to_string(),
+        },
+        Token {
+            id: TARI_TOKEN.to_string(),
+            network: Network::Tari,
+            kind: AssetKind::Native,
+            decimals: 6,
+            symbol: "tXTR".to_string(),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ERC20_ADDRESS: &str = "0x5fbdb2315678afecb367f032d93f642f64180aa3";
+    const TARI_RESOURCE_ADDRESS: &str =
+        "resource_0101010101010101010101010101010101010101010101010101010101010101";
+
+    fn parse_tokens(json: &str) -> Vec<Token> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn erc20_token() -> Token {
+        Token {
+            id: "usdc".to_string(),
+            network: Network::Ethereum,
+            kind: AssetKind::Erc20 {
+                contract_address: ERC20_ADDRESS.to_string(),
+            },
+            decimals: 6,
+            symbol: "USDC".to_string(),
+        }
+    }
+
+    #[test]
+    fn it_always_includes_the_native_tokens() {
+        let registry = TokenRegistry::new(vec![]).unwrap();
+
+        let eth = registry.get(ETH_WEI_TOKEN).unwrap();
+        assert_eq!(eth.network, Network::Ethereum);
+        assert_eq!(eth.kind, AssetKind::Native);
+        let tari = registry.get(TARI_TOKEN).unwrap();
+        assert_eq!(tari.network, Network::Tari);
+        assert_eq!(tari.kind, AssetKind::Native);
+        assert_eq!(registry.list().count(), 2);
+    }
+
+    #[test]
+    fn it_parses_the_tokens_of_the_config() {
+        let tokens = parse_tokens(&format!(
+            r#"[
+                {{"id": "usdc", "network": "ethereum", "kind": {{"type": "erc20", "contract_address": "{erc20}"}}, "decimals": 6, "symbol": "USDC"}},
+                {{"id": "punk", "network": "ethereum", "kind": {{"type": "erc721", "contract_address": "{erc20}", "token_id": "42"}}, "decimals": 0, "symbol": "PUNK"}},
+                {{"id": "gold", "network": "tari", "kind": {{"type": "tari_resource", "resource_address": "{tari}"}}, "decimals": 2, "symbol": "GLD"}},
+                {{"id": "badge", "network": "tari", "kind": {{"type": "tari_non_fungible", "resource_address": "{tari}", "token_id": "str:badge"}}, "decimals": 0, "symbol": "BDG"}},
+                {{"id": "secret", "network": "tari", "kind": {{"type": "tari_confidential", "resource_address": "{tari}", "view_key_id": 3}}, "decimals": 6, "symbol": "SCR"}}
+            ]"#,
+            erc20 = ERC20_ADDRESS,
+            tari = TARI_RESOURCE_ADDRESS
+        ));
+        let registry = TokenRegistry::new(tokens).unwrap();
+
+        assert_eq!(registry.list().count(), 7);
+        assert_eq!(registry.get("usdc").unwrap(), &erc20_token());
+        assert!(registry.get("punk").unwrap().is_non_fungible());
+        assert!(!registry.get("gold").unwrap().is_non_fungible());
+        assert!(registry.get("badge").unwrap().is_non_fungible());
+        assert_eq!(
+            registry.get("secret").unwrap().kind,
+            AssetKind::TariConfidential {
+                resource_address: TARI_RESOURCE_ADDRESS.to_string(),
+                view_key_id: Some(3),
+            }
+        );
+    }
+
+    #[test]
+    fn it_rejects_unknown_tokens() {
+        let registry = TokenRegistry::new(vec![]).unwrap();
+        assert!(registry.get("usdc").is_err());
+    }
+
+    #[test]
+    fn it_rejects_duplicated_tokens() {
+        assert!(TokenRegistry::new(vec![erc20_token(), erc20_token()]).is_err());
+
+        let mut token = erc20_token();
+        token.id = ETH_WEI_TOKEN.to_string();
+        assert!(TokenRegistry::new(vec![token]).is_err());
+    }
+
+    #[test]
+    fn it_rejects_assets_of_other_networks() {
+        let mut token = erc20_token();
+        token.network = Network::Tari;
+        assert!(TokenRegistry::new(vec![token]).is_err());
+    }
+
+    #[test]
+    fn it_rejects_invalid_addresses() {
+        let mut token = erc20_token();
+        token.kind = AssetKind::Erc20 {
+            contract_address: "0x1234".to_string(),
+        };
+        assert!(TokenRegistry::new(vec![token]).is_err());
+
+        let token = Token {
+            id: "gold".to_string(),
+            network: Network::Tari,
+            kind: AssetKind::TariResource {
+                resource_address: ERC20_ADDRESS.to_string(),
+            },
+            decimals: 2,
+            symbol: "GLD".to_string(),
+        };
+        assert!(TokenRegistry::new(vec![token]).is_err());
+    }
+
+    #[test]
+    fn it_rejects_invalid_nft_ids() {
+        let token = Token {
+            id: "badge".to_string(),
+            network: Network::Tari,
+            kind: AssetKind::TariNonFungible {
+                resource_address: TARI_RESOURCE_ADDRESS.to_string(),
+                token_id: "badge".to_string(),
+            },
+            decimals: 0,
+            symbol: "BDG".to_string(),
+        };
+        assert!(TokenRegistry::new(vec![token]).is_err());
+    }
+
+    #[test]
+    fn it_validates_the_decimals() {
+        let mut token = erc20_token();
+        token.decimals = MAX_DECIMALS + 1;
+        assert!(TokenRegistry::new(vec![token]).is_err());
+
+        let token = Token {
+            id: "punk".to_string(),
+            network: Network::Ethereum,
+            kind: AssetKind::Erc721 {
+                contract_address: ERC20_ADDRESS.to_string(),
+                token_id: "42".to_string(),
+            },
+            decimals: 1,
+            symbol: "PUNK".to_string(),
+        };
+        assert!(TokenRegistry::new(vec![token]).is_err());
+    }
+
+    #[test]
+    fn it_formats_the_amounts_with_the_decimals() {
+        let token = erc20_token();
+        assert_eq!(token.format_amount(0), "0 USDC");
+        assert_eq!(token.format_amount(1), "0.000001 USDC");
+        assert_eq!(token.format_amount(1_500_000), "1.5 USDC");
+        assert_eq!(token.format_amount(12_000_000), "12 USDC");
+
+        let registry = TokenRegistry::new(vec![]).unwrap();
+        let eth = registry.get(ETH_WEI_TOKEN).unwrap();
+        assert_eq!(eth.format_amount(u64::MAX), "18.446744073709551615 ETH");
+    }
+}
@@ -3,6 +3,7 @@ extern crate dotenv;
 use dotenv::dotenv;
 use ethereum::EthereumContractManager;
 use ethereum::Preimage;
+use ethereum::Timelock;
 use ethers::utils::hex;
 use ethers::{
     signers::{LocalWallet, Signer},
@@ -10,10 +11,13 @@ use ethers::{
     utils::parse_units,
 };
 use std::env;
+use tari::contract::TariAsset;
 use tari::contract::TariContractManager;
 use tari_crypto::ristretto::RistrettoPublicKey;
 use tari_crypto::tari_utilities::hex::Hex;
+use tari_template_lib::prelude::Amount;
 use tari_template_lib::prelude::TemplateAddress;
+use tari_template_lib::prelude::CONFIDENTIAL_TARI_RESOURCE_ADDRESS;
 
 #[tokio::main]
 async fn main() {
@@ -53,23 +57,22 @@ async fn main() {
     let alice_eth_wallet = eth_alice_private_key.parse::<LocalWallet>().unwrap();
     let bob_eth_wallet = eth_bob_private_key.parse::<LocalWallet>().unwrap();
     let timelock_eth = 100; // seconds
-    let hashlock = EthereumContractManager::create_hashlock(preimage);
-    let alice_eth_manager = EthereumContractManager::new(
-        alice_eth_wallet.clone(),
-        eth_rpc_url.clone(),
-        eth_contract_address.clone(),
-    )
-    .await
-    .unwrap();
+    let hashlock = hashlock::create_hashlock(&preimage);
+    let alice_eth_client = ethereum::new_client(alice_eth_wallet.clone(), eth_rpc_url.clone())
+        .await
+        .unwrap();
+    let alice_eth_manager =
+        EthereumContractManager::new(alice_eth_client, eth_contract_address.clone()).unwrap();
     let alice_eth_contract_id = alice_eth_manager
         .new_contract(
             eth_amount_wei,
             bob_eth_wallet.address(),
             hashlock,
-            timelock_eth,
+            Timelock::Relative(timelock_eth),
         )
         .await
-        .unwrap();
+        .unwrap()
+        .contract_id;
     println!(
         "    - Alice funds locked on Ethereum with contract_id = '{}'",
         hex::encode(alice_eth_contract_id)
@@ -87,7 +90,10 @@ async fn main() {
     let timelock_tari = 5; // epochs
     let contract_id_tari = bob_tari_contract_manager
         .create_lock_contract(
-            tari_amount,
+            TariAsset::Fungible {
+                resource_address: *CONFIDENTIAL_TARI_RESOURCE_ADDRESS,
+                amount: Amount(tari_amount),
+            },
             tari_alice_public_key.clone(),
             hashlock,
             timelock_tari,
@@ -124,13 +130,11 @@ async fn main() {
     println!("    - Bob sucessfully retrieves the preimage");
 
     println!("Bob will withdraws funds from Alice's contract on Ethereum");
-    let bob_eth_manager = EthereumContractManager::new(
-        bob_eth_wallet.clone(),
-        eth_rpc_url.clone(),
-        eth_contract_address.clone(),
-    )
-    .await
-    .unwrap();
+    let bob_eth_client = ethereum::new_client(bob_eth_wallet.clone(), eth_rpc_url.clone())
+        .await
+        .unwrap();
+    let bob_eth_manager =
+        EthereumContractManager::new(bob_eth_client, eth_contract_address.clone()).unwrap();
     bob_eth_manager
         .withdraw(alice_eth_contract_id, revealed_preimage)
         .await
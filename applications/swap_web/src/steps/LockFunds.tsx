@@ -48,8 +48,9 @@ export default function LockFunds(props) {
     }
   }
 
-  async function getClientAddress() {
-    switch (toToken) {
+  // our address in the network of the token
+  async function getClientAddress(token) {
+    switch (token) {
       case "eth.wei":
         let provider = new ethers.providers.Web3Provider(window.ethereum);
         provider.send("eth_requestAccounts", [])
@@ -125,7 +126,10 @@ export default function LockFunds(props) {
   }
 
   const requestSwapFromProvider = async (provider_address, hashlock) => {
-    const client_address = await getClientAddress();
+    // the provider sends their funds to our address in the requested token network
+    // and checks that our contract is sent from our address in the provided token network
+    const client_address = await getClientAddress(toToken);
+    const client_sender_address = await getClientAddress(fromToken);
 
     const body = {
       jsonrpc: "2.0",
@@ -133,6 +137,7 @@ export default function LockFunds(props) {
       id: 1,
       params: {
         client_address,
+        client_sender_address,
         hashlock,
         position: {
           provided_token: fromToken,
@@ -0,0 +1,210 @@
+use std::sync::Arc;
+
+use ethers::prelude::abigen;
+use ethers::types::Address;
+use ethers::types::U256;
+
+use crate::call_error;
+use crate::find_event;
+use crate::get_chain_time;
+use crate::parse_address;
+use crate::resolve_timelock;
+use crate::send_transaction;
+use crate::wallet_address;
+use crate::ContractId;
+use crate::EthereumClient;
+use crate::EthereumError;
+use crate::Hashlock;
+use crate::Preimage;
+use crate::Timelock;
+
+abigen!(HashedTimelockERC20, "abi/HashedTimelockERC20.json");
+
+// We only need the methods to let the HTLC contract move our tokens
+abigen!(
+    IERC20,
+    r#"[
+        function approve(address spender, uint256 amount) external returns (bool)
+        function allowance(address owner, address spender) external view returns (uint256)
+        function balanceOf(address account) external view returns (uint256)
+    ]"#
+);
+
+// Manages the HTLCs of a single ERC20 token in the HashedTimelockERC20 smart contract
+pub struct EthereumErc20ContractManager {
+    pub(crate) client: Arc<EthereumClient>,
+    htlc_contract_address: Address,
+    token_contract_address: Address,
+}
+
+impl EthereumErc20ContractManager {
+    pub fn new(
+        client: Arc<EthereumClient>,
+        htlc_contract_address: String,
+        token_contract_address: String,
+    ) -> Result<Self, EthereumError> {
+        let htlc_contract_address = parse_address(htlc_contract_address)?;
+        let token_contract_address = parse_address(token_contract_address)?;
+
+        Ok(Self {
+            client,
+            htlc_contract_address,
+            token_contract_address,
+        })
+    }
+
+    pub fn token_contract_address(&self) -> Address {
+        self.token_contract_address
+    }
+
+    // The HTLC contract pulls the tokens from our account, so it must be allowed to spend them first
+    pub async fn new_contract(
+        &self,
+        amount: U256,
+        receiver: Address,
+        hashlock: Hashlock,
+        timelock: Timelock,
+    ) -> Result<Htlcerc20NewFilter, EthereumError> {
+        let balance = self.balance().await?;
+        if balance < amount {
+            return Err(EthereumError::InsufficientBalance {
+                token: self.token_contract_address,
+                balance,
+                amount,
+            });
+        }
+
+        if self.allowance().await? < amount {
+            self.approve(amount).await?;
+        }
+
+        let contract = self.htlc_contract();
+
+        let timelock = resolve_timelock(&self.client, timelock).await?;
+
+        let tx = contract.new_contract(
+            receiver,
+            hashlock,
+            timelock.into(),
+            self.token_contract_address,
+            amount,
+        );
+        let receipt = send_transaction(tx).await?;
+        // The token contract also emits events on the transfer, so we filter by the HTLC contract
+        find_event::<Htlcerc20NewFilter>(&receipt, self.htlc_contract_address)
+    }
+
+    pub async fn withdraw(
+        &self,
+        contract_id: ContractId,
+        preimage: Preimage,
+    ) -> Result<(), EthereumError> {
+        let contract = self.htlc_contract();
+        send_transaction(contract.withdraw(contract_id, preimage)).await?;
+        Ok(())
+    }
+
+    pub async fn refund(&self, contract_id: ContractId) -> Result<(), EthereumError> {
+        let contract = self.htlc_contract();
+        send_transaction(contract.refund(contract_id)).await?;
+        Ok(())
+    }
+
+    // The preimage is only available after the receiver withdraws the funds
+    pub async fn get_preimage(
+        &self,
+        contract_id: ContractId,
+    ) -> Result<Option<Preimage>, EthereumError> {
+        let contract = self.get_contract(contract_id).await?;
+        if !contract.withdrawn {
+            return Ok(None);
+        }
+        Ok(Some(contract.preimage))
+    }
+
+    pub async fn get_contract(
+        &self,
+        contract_id: ContractId,
+    ) -> Result<EthereumErc20Contract, EthereumError> {
+        let contract = self.htlc_contract();
+        let res = contract
+            .get_contract(contract_id)
+            .call()
+            .await
+            .map_err(call_error)?;
+
+        // The fields follow the same order as in the return type of the "getContract" solidity method
+        Ok(EthereumErc20Contract {
+            sender: res.0,
+            receiver: res.1,
+            token_contract: res.2,
+            amount: res.3,
+            hashlock: res.4,
+            timelock: res.5,
+            withdrawn: res.6,
+            refunded: res.7,
+            preimage: res.8,
+        })
+    }
+
+    // A contract can be refunded if the funds are still locked and the timelock has passed
+    pub async fn is_refundable(&self, contract_id: ContractId) -> Result<bool, EthereumError> {
+        let contract = self.get_contract(contract_id).await?;
+        if contract.withdrawn || contract.refunded {
+            return Ok(false);
+        }
+
+        let chain_time = self.current_chain_time().await?;
+        Ok(contract.timelock <= chain_time.into())
+    }
+
+    pub async fn current_chain_time(&self) -> Result<u64, EthereumError> {
+        get_chain_time(&self.client).await
+    }
+
+    pub async fn balance(&self) -> Result<U256, EthereumError> {
+        self.token_contract()
+            .balance_of(wallet_address(&self.client))
+            .call()
+            .await
+            .map_err(call_error)
+    }
+
+    // The amount of our tokens that the HTLC contract is allowed to spend
+    pub async fn allowance(&self) -> Result<U256, EthereumError> {
+        self.token_contract()
+            .allowance(wallet_address(&self.client), self.htlc_contract_address)
+            .call()
+            .await
+            .map_err(call_error)
+    }
+
+    pub async fn approve(&self, amount: U256) -> Result<(), EthereumError> {
+        let token = self.token_contract();
+        send_transaction(token.approve(self.htlc_contract_address, amount)).await?;
+        Ok(())
+    }
+
+    fn htlc_contract(&self) -> HashedTimelockERC20<EthereumClient> {
+        HashedTimelockERC20::new(self.htlc_contract_address, self.client.clone())
+    }
+
+    fn token_contract(&self) -> IERC20<EthereumClient> {
+        IERC20::new(self.token_contract_address, self.client.clone())
+    }
+}
+
+// Contract information as stored in the HashedTimelockERC20 smart contract
+// If the contract does not exist, all the fields will have their default value
+#[derive(Debug, Clone)]
+pub struct EthereumErc20Contract {
+    pub sender: Address,
+    pub receiver: Address,
+    pub token_contract: Address,
+    pub amount: U256,
+    pub hashlock: Hashlock,
+    pub timelock: U256,
+    pub withdrawn: bool,
+    pub refunded: bool,
+    pub preimage: Preimage,
+}
@@ -0,0 +1,235 @@
+use std::sync::Arc;
+
+use ethers::prelude::abigen;
+use ethers::types::Address;
+use ethers::types::U256;
+
+use crate::call_error;
+use crate::find_event;
+use crate::get_chain_time;
+use crate::parse_address;
+use crate::resolve_timelock;
+use crate::send_transaction;
+use crate::wallet_address;
+use crate::ContractId;
+use crate::EthereumClient;
+use crate::EthereumError;
+use crate::Hashlock;
+use crate::Preimage;
+use crate::Timelock;
+
+abigen!(HashedTimelockERC721, "abi/HashedTimelockERC721.json");
+
+// We only need the methods to let the HTLC contract move our NFTs
+abigen!(
+    IERC721,
+    r#"[
+        function ownerOf(uint256 tokenId) external view returns (address)
+        function approve(address to, uint256 tokenId) external
+        function getApproved(uint256 tokenId) external view returns (address)
+        function setApprovalForAll(address operator, bool approved) external
+        function isApprovedForAll(address owner, address operator) external view returns (bool)
+    ]"#
+);
+
+// Manages the HTLCs of a single NFT in the HashedTimelockERC721 smart contract
+pub struct EthereumErc721ContractManager {
+    pub(crate) client: Arc<EthereumClient>,
+    htlc_contract_address: Address,
+    token_contract_address: Address,
+    token_id: U256,
+}
+
+impl EthereumErc721ContractManager {
+    pub fn new(
+        client: Arc<EthereumClient>,
+        htlc_contract_address: String,
+        token_contract_address: String,
+        token_id: U256,
+    ) -> Result<Self, EthereumError> {
+        let htlc_contract_address = parse_address(htlc_contract_address)?;
+        let token_contract_address = parse_address(token_contract_address)?;
+
+        Ok(Self {
+            client,
+            htlc_contract_address,
+            token_contract_address,
+            token_id,
+        })
+    }
+
+    pub fn token_contract_address(&self) -> Address {
+        self.token_contract_address
+    }
+
+    pub fn token_id(&self) -> U256 {
+        self.token_id
+    }
+
+    // The HTLC contract pulls the NFT from our account, so it must be allowed to transfer it first
+    pub async fn new_contract(
+        &self,
+        receiver: Address,
+        hashlock: Hashlock,
+        timelock: Timelock,
+    ) -> Result<Htlcerc721NewFilter, EthereumError> {
+        let owner = self.owner().await?;
+        if owner != wallet_address(&self.client) {
+            return Err(EthereumError::NotTokenOwner {
+                token: self.token_contract_address,
+                token_id: self.token_id,
+                owner,
+            });
+        }
+
+        if !self.is_approved().await? {
+            self.approve().await?;
+        }
+
+        let contract = self.htlc_contract();
+
+        let timelock = resolve_timelock(&self.client, timelock).await?;
+
+        let tx = contract.new_contract(
+            receiver,
+            hashlock,
+            timelock.into(),
+            self.token_contract_address,
+            self.token_id,
+        );
+        let receipt = send_transaction(tx).await?;
+        // The token contract also emits events on the transfer, so we filter by the HTLC contract
+        find_event::<Htlcerc721NewFilter>(&receipt, self.htlc_contract_address)
+    }
+
+    pub async fn withdraw(
+        &self,
+        contract_id: ContractId,
+        preimage: Preimage,
+    ) -> Result<(), EthereumError> {
+        let contract = self.htlc_contract();
+        send_transaction(contract.withdraw(contract_id, preimage)).await?;
+        Ok(())
+    }
+
+    pub async fn refund(&self, contract_id: ContractId) -> Result<(), EthereumError> {
+        let contract = self.htlc_contract();
+        send_transaction(contract.refund(contract_id)).await?;
+        Ok(())
+    }
+
+    // The preimage is only available after the receiver withdraws the funds
+    pub async fn get_preimage(
+        &self,
+        contract_id: ContractId,
+    ) -> Result<Option<Preimage>, EthereumError> {
+        let contract = self.get_contract(contract_id).await?;
+        if !contract.withdrawn {
+            return Ok(None);
+        }
+        Ok(Some(contract.preimage))
+    }
+
+    pub async fn get_contract(
+        &self,
+        contract_id: ContractId,
+    ) -> Result<EthereumErc721Contract, EthereumError> {
+        let contract = self.htlc_contract();
+        let res = contract
+            .get_contract(contract_id)
+            .call()
+            .await
+            .map_err(call_error)?;
+
+        // The fields follow the same order as in the return type of the "getContract" solidity method
+        Ok(EthereumErc721Contract {
+            sender: res.0,
+            receiver: res.1,
+            token_contract: res.2,
+            token_id: res.3,
+            hashlock: res.4,
+            timelock: res.5,
+            withdrawn: res.6,
+            refunded: res.7,
+            preimage: res.8,
+        })
+    }
+
+    // A contract can be refunded if the NFT is still locked and the timelock has passed
+    pub async fn is_refundable(&self, contract_id: ContractId) -> Result<bool, EthereumError> {
+        let contract = self.get_contract(contract_id).await?;
+        if contract.withdrawn || contract.refunded {
+            return Ok(false);
+        }
+
+        let chain_time = self.current_chain_time().await?;
+        Ok(contract.timelock <= chain_time.into())
+    }
+
+    pub async fn current_chain_time(&self) -> Result<u64, EthereumError> {
+        get_chain_time(&self.client).await
+    }
+
+    pub async fn owner(&self) -> Result<Address, EthereumError> {
+        self.token_contract()
+            .owner_of(self.token_id)
+            .call()
+            .await
+            .map_err(call_error)
+    }
+
+    // The HTLC contract can transfer the NFT if it was approved for it or for all our NFTs in the collection
+    pub async fn is_approved(&self) -> Result<bool, EthereumError> {
+        let token = self.token_contract();
+        let approved = token
+            .get_approved(self.token_id)
+            .call()
+            .await
+            .map_err(call_error)?;
+        if approved == self.htlc_contract_address {
+            return Ok(true);
+        }
+
+        token
+            .is_approved_for_all(wallet_address(&self.client), self.htlc_contract_address)
+            .call()
+            .await
+            .map_err(call_error)
+    }
+
+    pub async fn approve(&self) -> Result<(), EthereumError> {
+        let token = self.token_contract();
+        send_transaction(token.approve(self.htlc_contract_address, self.token_id)).await?;
+        Ok(())
+    }
+
+    // Allows the HTLC contract to transfer any of our NFTs in the collection, to avoid approving each swap
+    pub async fn set_approval_for_all(&self, approved: bool) -> Result<(), EthereumError> {
+        let token = self.token_contract();
+        send_transaction(token.set_approval_for_all(self.htlc_contract_address, approved)).await?;
+        Ok(())
+    }
+
+    fn htlc_contract(&self) -> HashedTimelockERC721<EthereumClient> {
+        HashedTimelockERC721::new(self.htlc_contract_address, self.client.clone())
+    }
+
+    fn token_contract(&self) -> IERC721<EthereumClient> {
+        IERC721::new(self.token_contract_address, self.client.clone())
+    }
+}
+
+// Contract information as stored in the HashedTimelockERC721 smart contract
+// If the contract does not exist, all the fields will have their default value
+#[derive(Debug, Clone)]
+pub struct EthereumErc721Contract {
+    pub sender: Address,
+    pub receiver: Address,
+    pub token_contract: Address,
+    pub token_id: U256,
+    pub hashlock: Hashlock,
+    pub timelock: U256,
+    pub withdrawn: bool,
+    pub refunded: bool,
+    pub preimage: Preimage,
+}
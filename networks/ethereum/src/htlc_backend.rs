@@ -0,0 +1,518 @@
+use async_trait::async_trait;
+use ethers::types::Address;
+use ethers::types::U256;
+use ethers::utils::hex;
+use htlc::ContractId;
+use htlc::ContractState;
+use htlc::ContractStatus;
+use htlc::HashFunction;
+use htlc::Hashlock;
+use htlc::HtlcBackend;
+use htlc::HtlcError;
+use htlc::Preimage;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::wallet_address;
+use crate::EthereumContract;
+use crate::EthereumContractManager;
+use crate::EthereumErc20ContractManager;
+use crate::EthereumErc721ContractManager;
+use crate::EthereumError;
+use crate::Timelock;
+
+const NETWORK: &str = "Ethereum";
+// all our HTLC contracts check the preimages with "sha256"
+const HASH_FUNCTION: HashFunction = HashFunction::Sha256;
+
+// We call the inherent methods with their full path, as some of them have the same names as the trait ones
+#[async_trait]
+impl HtlcBackend for EthereumContractManager {
+    fn address(&self) -> String {
+        format!("{:?}", wallet_address(&self.client))
+    }
+
+    fn hash_function(&self) -> HashFunction {
+        HASH_FUNCTION
+    }
+
+    async fn lock(
+        &mut self,
+        amount: u64,
+        receiver: &str,
+        hashlock: Hashlock,
+        timelock: u64,
+    ) -> Result<ContractId, HtlcError> {
+        let receiver = parse_address(receiver)?;
+
+        let event = EthereumContractManager::new_contract(
+            self,
+            U256::from(amount),
+            receiver,
+            hashlock,
+            Timelock::Absolute(timelock),
+        )
+        .await?;
+        Ok(hex::encode(event.contract_id))
+    }
+
+    async fn find_lock(
+        &mut self,
+        amount: u64,
+        receiver: &str,
+        hashlock: Hashlock,
+        timelock: u64,
+    ) -> Result<Option<ContractId>, HtlcError> {
+        let contract_id = derive_contract_id(&[
+            wallet_address(&self.client).as_bytes(),
+            parse_address(receiver)?.as_bytes(),
+            &u256_bytes(U256::from(amount)),
+            &hashlock,
+            &u256_bytes(U256::from(timelock)),
+        ]);
+        let contract = EthereumContractManager::get_contract(self, contract_id).await?;
+        Ok((!contract.sender.is_zero()).then(|| hex::encode(contract_id)))
+    }
+
+    async fn withdraw(
+        &mut self,
+        contract_id: &ContractId,
+        preimage: Preimage,
+    ) -> Result<(), HtlcError> {
+        let contract_id = parse_contract_id(contract_id)?;
+        EthereumContractManager::withdraw(self, contract_id, preimage).await?;
+        Ok(())
+    }
+
+    async fn refund(&mut self, contract_id: &ContractId) -> Result<(), HtlcError> {
+        let contract_id = parse_contract_id(contract_id)?;
+        EthereumContractManager::refund(self, contract_id).await?;
+        Ok(())
+    }
+
+    async fn get_contract_state(
+        &mut self,
+        contract_id: &ContractId,
+    ) -> Result<ContractState, HtlcError> {
+        let contract =
+            EthereumContractManager::get_contract(self, parse_contract_id(contract_id)?).await?;
+        to_contract_state(contract_id, contract)
+    }
+
+    async fn get_preimage(
+        &mut self,
+        contract_id: &ContractId,
+    ) -> Result<Option<Preimage>, HtlcError> {
+        let contract_id = parse_contract_id(contract_id)?;
+        let preimage = EthereumContractManager::get_preimage(self, contract_id).await?;
+        Ok(preimage)
+    }
+
+    async fn is_refundable(&mut self, contract_id: &ContractId) -> Result<bool, HtlcError> {
+        let contract_id = parse_contract_id(contract_id)?;
+        let is_refundable = EthereumContractManager::is_refundable(self, contract_id).await?;
+        Ok(is_refundable)
+    }
+
+    async fn balance(&mut self) -> Result<u64, HtlcError> {
+        let balance = EthereumContractManager::balance(self).await?;
+        Ok(saturating_u64(balance))
+    }
+
+    async fn current_chain_time(&mut self) -> Result<u64, HtlcError> {
+        let chain_time = EthereumContractManager::current_chain_time(self).await?;
+        Ok(chain_time)
+    }
+}
+
+#[async_trait]
+impl HtlcBackend for EthereumErc20ContractManager {
+    fn address(&self) -> String {
+        format!("{:?}", wallet_address(&self.client))
+    }
+
+    fn hash_function(&self) -> HashFunction {
+        HASH_FUNCTION
+    }
+
+    async fn lock(
+        &mut self,
+        amount: u64,
+        receiver: &str,
+        hashlock: Hashlock,
+        timelock: u64,
+    ) -> Result<ContractId, HtlcError> {
+        let receiver = parse_address(receiver)?;
+
+        let event = EthereumErc20ContractManager::new_contract(
+            self,
+            U256::from(amount),
+            receiver,
+            hashlock,
+            Timelock::Absolute(timelock),
+        )
+        .await?;
+        Ok(hex::encode(event.contract_id))
+    }
+
+    async fn find_lock(
+        &mut self,
+        amount: u64,
+        receiver: &str,
+        hashlock: Hashlock,
+        timelock: u64,
+    ) -> Result<Option<ContractId>, HtlcError> {
+        let contract_id = derive_contract_id(&[
+            wallet_address(&self.client).as_bytes(),
+            parse_address(receiver)?.as_bytes(),
+            self.token_contract_address().as_bytes(),
+            &u256_bytes(U256::from(amount)),
+            &hashlock,
+            &u256_bytes(U256::from(timelock)),
+        ]);
+        let contract = EthereumErc20ContractManager::get_contract(self, contract_id).await?;
+        Ok((!contract.sender.is_zero()).then(|| hex::encode(contract_id)))
+    }
+
+    async fn withdraw(
+        &mut self,
+        contract_id: &ContractId,
+        preimage: Preimage,
+    ) -> Result<(), HtlcError> {
+        let contract_id = parse_contract_id(contract_id)?;
+        EthereumErc20ContractManager::withdraw(self, contract_id, preimage).await?;
+        Ok(())
+    }
+
+    async fn refund(&mut self, contract_id: &ContractId) -> Result<(), HtlcError> {
+        let contract_id = parse_contract_id(contract_id)?;
+        EthereumErc20ContractManager::refund(self, contract_id).await?;
+        Ok(())
+    }
+
+    async fn get_contract_state(
+        &mut self,
+        contract_id: &ContractId,
+    ) -> Result<ContractState, HtlcError> {
+        let contract =
+            EthereumErc20ContractManager::get_contract(self, parse_contract_id(contract_id)?)
+                .await?;
+
+        // All the tokens share the same HTLC contract, so we must check that the funds are in our token
+        if !contract.sender.is_zero() && contract.token_contract != self.token_contract_address() {
+            return Err(HtlcError::InvalidAsset {
+                expected: format!("{:?}", self.token_contract_address()),
+                found: format!("{:?}", contract.token_contract),
+            });
+        }
+
+        let contract = EthereumContract {
+            sender: contract.sender,
+            receiver: contract.receiver,
+            amount: contract.amount,
+            hashlock: contract.hashlock,
+            timelock: contract.timelock,
+            withdrawn: contract.withdrawn,
+            refunded: contract.refunded,
+            preimage: contract.preimage,
+        };
+        to_contract_state(contract_id, contract)
+    }
+
+    async fn get_preimage(
+        &mut self,
+        contract_id: &ContractId,
+    ) -> Result<Option<Preimage>, HtlcError> {
+        let contract_id = parse_contract_id(contract_id)?;
+        let preimage = EthereumErc20ContractManager::get_preimage(self, contract_id).await?;
+        Ok(preimage)
+    }
+
+    async fn is_refundable(&mut self, contract_id: &ContractId) -> Result<bool, HtlcError> {
+        let contract_id = parse_contract_id(contract_id)?;
+        let is_refundable = EthereumErc20ContractManager::is_refundable(self, contract_id).await?;
+        Ok(is_refundable)
+    }
+
+    async fn balance(&mut self) -> Result<u64, HtlcError> {
+        let balance = EthereumErc20ContractManager::balance(self).await?;
+        Ok(saturating_u64(balance))
+    }
+
+    async fn current_chain_time(&mut self) -> Result<u64, HtlcError> {
+        let chain_time = EthereumErc20ContractManager::current_chain_time(self).await?;
+        Ok(chain_time)
+    }
+}
+
+// NFTs cannot be split, so the amount of all the contracts is always one
+#[async_trait]
+impl HtlcBackend for EthereumErc721ContractManager {
+    fn address(&self) -> String {
+        format!("{:?}", wallet_address(&self.client))
+    }
+
+    fn hash_function(&self) -> HashFunction {
+        HASH_FUNCTION
+    }
+
+    async fn lock(
+        &mut self,
+        amount: u64,
+        receiver: &str,
+        hashlock: Hashlock,
+        timelock: u64,
+    ) -> Result<ContractId, HtlcError> {
+        if amount != 1 {
+            return Err(HtlcError::InvalidAmount(amount));
+        }
+        let receiver = parse_address(receiver)?;
+
+        let event = EthereumErc721ContractManager::new_contract(
+            self,
+            receiver,
+            hashlock,
+            Timelock::Absolute(timelock),
+        )
+        .await?;
+        Ok(hex::encode(event.contract_id))
+    }
+
+    async fn find_lock(
+        &mut self,
+        amount: u64,
+        receiver: &str,
+        hashlock: Hashlock,
+        timelock: u64,
+    ) -> Result<Option<ContractId>, HtlcError> {
+        if amount != 1 {
+            return Err(HtlcError::InvalidAmount(amount));
+        }
+        let contract_id = derive_contract_id(&[
+            wallet_address(&self.client).as_bytes(),
+            parse_address(receiver)?.as_bytes(),
+            self.token_contract_address().as_bytes(),
+            &u256_bytes(self.token_id()),
+            &hashlock,
+            &u256_bytes(U256::from(timelock)),
+        ]);
+        let contract = EthereumErc721ContractManager::get_contract(self, contract_id).await?;
+        Ok((!contract.sender.is_zero()).then(|| hex::encode(contract_id)))
+    }
+
+    async fn withdraw(
+        &mut self,
+        contract_id: &ContractId,
+        preimage: Preimage,
+    ) -> Result<(), HtlcError> {
+        let contract_id = parse_contract_id(contract_id)?;
+        EthereumErc721ContractManager::withdraw(self, contract_id, preimage).await?;
+        Ok(())
+    }
+
+    async fn refund(&mut self, contract_id: &ContractId) -> Result<(), HtlcError> {
+        let contract_id = parse_contract_id(contract_id)?;
+        EthereumErc721ContractManager::refund(self, contract_id).await?;
+        Ok(())
+    }
+
+    async fn get_contract_state(
+        &mut self,
+        contract_id: &ContractId,
+    ) -> Result<ContractState, HtlcError> {
+        let contract =
+            EthereumErc721ContractManager::get_contract(self, parse_contract_id(contract_id)?)
+                .await?;
+
+        // All the NFTs share the same HTLC contract, so we must check that it locks our NFT
+        let is_our_token = contract.token_contract == self.token_contract_address()
+            && contract.token_id == self.token_id();
+        if !contract.sender.is_zero() && !is_our_token {
+            return Err(HtlcError::InvalidAsset {
+                expected: format!("{:?}/{}", self.token_contract_address(), self.token_id()),
+                found: format!("{:?}/{}", contract.token_contract, contract.token_id),
+            });
+        }
+
+        let contract = EthereumContract {
+            sender: contract.sender,
+            receiver: contract.receiver,
+            amount: U256::one(),
+            hashlock: contract.hashlock,
+            timelock: contract.timelock,
+            withdrawn: contract.withdrawn,
+            refunded: contract.refunded,
+            preimage: contract.preimage,
+        };
+        to_contract_state(contract_id, contract)
+    }
+
+    async fn get_preimage(
+        &mut self,
+        contract_id: &ContractId,
+    ) -> Result<Option<Preimage>, HtlcError> {
+        let contract_id = parse_contract_id(contract_id)?;
+        let preimage = EthereumErc721ContractManager::get_preimage(self, contract_id).await?;
+        Ok(preimage)
+    }
+
+    async fn is_refundable(&mut self, contract_id: &ContractId) -> Result<bool, HtlcError> {
+        let contract_id = parse_contract_id(contract_id)?;
+        let is_refundable = EthereumErc721ContractManager::is_refundable(self, contract_id).await?;
+        Ok(is_refundable)
+    }
+
+    async fn balance(&mut self) -> Result<u64, HtlcError> {
+        let owner = EthereumErc721ContractManager::owner(self).await?;
+        Ok(u64::from(owner == wallet_address(&self.client)))
+    }
+
+    async fn current_chain_time(&mut self) -> Result<u64, HtlcError> {
+        let chain_time = EthereumErc721ContractManager::current_chain_time(self).await?;
+        Ok(chain_time)
+    }
+}
+
+// The transaction failures are kept apart, so clients can know if it is worth retrying
+impl From<EthereumError> for HtlcError {
+    fn from(e: EthereumError) -> Self {
+        match e {
+            EthereumError::SendError { detail } => HtlcError::SendError {
+                network: NETWORK.to_string(),
+                detail,
+            },
+            EthereumError::TransactionReverted { reason, .. } => HtlcError::TransactionReverted {
+                network: NETWORK.to_string(),
+                reason: reason.unwrap_or_else(|| "no reason given".to_string()),
+            },
+            EthereumError::MissingReceipt { tx_hash, detail } => HtlcError::MissingReceipt {
+                network: NETWORK.to_string(),
+                tx_id: format!("{:?}", tx_hash),
+                detail,
+            },
+            EthereumError::TransactionDropped { tx_hash } => HtlcError::TransactionDropped {
+                network: NETWORK.to_string(),
+                tx_id: format!("{:?}", tx_hash),
+            },
+            _ => network_error(e),
+        }
+    }
+}
+
+fn network_error(e: impl ToString) -> HtlcError {
+    HtlcError::NetworkError {
+        network: NETWORK.to_string(),
+        detail: e.to_string(),
+    }
+}
+
+fn to_contract_state(
+    contract_id: &ContractId,
+    contract: EthereumContract,
+) -> Result<ContractState, HtlcError> {
+    // The smart contracts return default values for unknown contract ids
+    if contract.sender.is_zero() {
+        return Err(HtlcError::ContractNotFound(contract_id.clone()));
+    }
+
+    let status = if contract.withdrawn {
+        ContractStatus::Withdrawn
+    } else if contract.refunded {
+        ContractStatus::Refunded
+    } else {
+        ContractStatus::Locked
+    };
+
+    Ok(ContractState {
+        sender: format!("{:?}", contract.sender),
+        receiver: format!("{:?}", contract.receiver),
+        amount: contract
+            .amount
+            .try_into()
+            .map_err(|_| network_error(format!("Invalid amount {}", contract.amount)))?,
+        confidential: false,
+        hashlock: contract.hashlock,
+        timelock: contract
+            .timelock
+            .try_into()
+            .map_err(|_| network_error(format!("Invalid timelock {}", contract.timelock)))?,
+        status,
+        preimage: contract.withdrawn.then_some(contract.preimage),
+    })
+}
+
+// Same derivation as in the HTLC smart contracts, the sha256 of the sender followed by the packed arguments of "newContract"
+fn derive_contract_id(parts: &[&[u8]]) -> crate::ContractId {
+    parts
+        .iter()
+        .fold(Sha256::new(), |hasher, part| hasher.chain_update(part))
+        .finalize()
+        .into()
+}
+
+// "abi.encodePacked" encodes the integers as 32 big endian bytes
+fn u256_bytes(value: U256) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    bytes
+}
+
+// a single swap can never lock more than an u64, so a higher balance makes no difference
+fn saturating_u64(value: U256) -> u64 {
+    value.min(U256::from(u64::MAX)).as_u64()
+}
+
+fn parse_address(input: &str) -> Result<Address, HtlcError> {
+    input
+        .parse::<Address>()
+        .map_err(|_| HtlcError::InvalidAddress(input.to_string()))
+}
+
+fn parse_contract_id(input: &ContractId) -> Result<crate::ContractId, HtlcError> {
+    let contract_id_hex = input.trim_start_matches("0x");
+    hex::decode(contract_id_hex)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| HtlcError::InvalidContractId(input.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "abi.encodePacked" lays out the addresses in 20 bytes and the integers in 32, without any padding between them
+    #[test]
+    fn it_derives_the_contract_ids_of_the_smart_contracts() {
+        let sender = Address::repeat_byte(0x11);
+        let receiver = Address::repeat_byte(0x22);
+        let hashlock = [0x33; 32];
+        let timelock = U256::from(1_700_000_000u64);
+
+        // sha256(abi.encodePacked(msg.sender, _receiver, msg.value, _hashlock, _timelock))
+        let contract_id = derive_contract_id(&[
+            sender.as_bytes(),
+            receiver.as_bytes(),
+            &u256_bytes(U256::exp10(18)),
+            &hashlock,
+            &u256_bytes(timelock),
+        ]);
+        assert_eq!(
+            hex::encode(contract_id),
+            "263c7742ef1d095a045feeb3f47263fcf8c918701c9761c60741883a6df59d6a"
+        );
+
+        // sha256(abi.encodePacked(msg.sender, _receiver, _tokenContract, _amount, _hashlock, _timelock))
+        let contract_id = derive_contract_id(&[
+            sender.as_bytes(),
+            receiver.as_bytes(),
+            Address::repeat_byte(0x44).as_bytes(),
+            &u256_bytes(U256::exp10(18)),
+            &hashlock,
+            &u256_bytes(timelock),
+        ]);
+        assert_eq!(
+            hex::encode(contract_id),
+            "ad41ab3f62e086b6a536ae5d276c6a892a4125a9ac4e7255cc0f1fe9f4600aef"
+        );
+    }
+}
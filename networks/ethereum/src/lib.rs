@@ -1,40 +1,77 @@
+use std::sync::Arc;
 use std::time::Duration;
-use std::time::SystemTime;
 
+use ethers::abi::Detokenize;
+use ethers::contract::ContractCall;
+use ethers::contract::ContractError;
+use ethers::contract::EthEvent;
+use ethers::middleware::NonceManagerMiddleware;
 use ethers::prelude::abigen;
 use ethers::prelude::Http;
 use ethers::prelude::Provider;
 use ethers::prelude::SignerMiddleware;
+use ethers::providers::Middleware;
 use ethers::signers::LocalWallet;
+use ethers::signers::Signer;
 use ethers::types::Address;
+use ethers::types::BlockNumber;
+use ethers::types::TransactionReceipt;
 use ethers::types::H256;
 use ethers::types::U256;
-use sha2::Digest;
-use sha2::Sha256;
+use ethers::types::U64;
 use thiserror::Error;
 
-type ByteArray32 = [u8; 32];
-pub type ContractId = ByteArray32;
-pub type Preimage = ByteArray32;
-pub type Hashlock = ByteArray32;
+mod erc20;
+mod erc721;
+mod htlc_backend;
+
+pub use erc20::{EthereumErc20Contract, EthereumErc20ContractManager, Htlcerc20NewFilter};
+pub use erc721::{EthereumErc721Contract, EthereumErc721ContractManager, Htlcerc721NewFilter};
+pub use hashlock::{Hashlock, Preimage};
+
+pub type ContractId = [u8; 32];
+
+abigen!(HashedTimelock, "abi/HashedTimelock.json");
+
+// the transaction may still be mined after we stop waiting, see "EthereumError::MissingReceipt"
+const RECEIPT_TIMEOUT: Duration = Duration::from_secs(300);
+
+// The smart contracts store the timelocks as unix timestamps, checked against the block timestamps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timelock {
+    Absolute(u64),
+    // seconds after the timestamp of the latest block
+    Relative(u64),
+}
+
+// All the managers of a wallet must share the same client (see "new_client")
+pub type EthereumClient = NonceManagerMiddleware<SignerMiddleware<Provider<Http>, LocalWallet>>;
+
+// The nonces are tracked locally, as fetching them for each transaction would reuse them in concurrent sends
+pub async fn new_client(
+    wallet: LocalWallet,
+    rpc_url: String,
+) -> Result<Arc<EthereumClient>, EthereumError> {
+    let provider = parse_rpc_url(rpc_url)?;
+    let address = wallet.address();
+    let signer = SignerMiddleware::new_with_provider_chain(provider, wallet)
+        .await
+        .map_err(|e| EthereumError::WalletError {
+            detail: e.to_string(),
+        })?;
+    Ok(Arc::new(NonceManagerMiddleware::new(signer, address)))
+}
 
 pub struct EthereumContractManager {
-    client: SignerMiddleware<Provider<Http>, LocalWallet>,
+    client: Arc<EthereumClient>,
     eth_contract_address: Address,
 }
 
 impl EthereumContractManager {
-    pub async fn new(
-        wallet: LocalWallet,
-        rpc_url: String,
+    pub fn new(
+        client: Arc<EthereumClient>,
         eth_contract_address: String,
     ) -> Result<Self, EthereumError> {
-        let provider = parse_rpc_url(rpc_url)?;
-        let client = SignerMiddleware::new_with_provider_chain(provider.clone(), wallet.clone())
-            .await
-            .map_err(|e| EthereumError::WalletError {
-                detail: e.to_string(),
-            })?;
         let eth_contract_address = parse_address(eth_contract_address)?;
 
         Ok(Self {
@@ -43,43 +80,24 @@ impl EthereumContractManager {
         })
     }
 
-    pub fn create_hashlock(preimage: Preimage) -> Hashlock {
-        let mut hasher = Sha256::new();
-        hasher.update(preimage);
-        hasher.finalize().into()
-    }
-
+    // The returned event includes the effective timelock of the contract
     pub async fn new_contract(
         &self,
         amount_wei: U256,
         receiver: Address,
         hashlock: Hashlock,
-        timelock: u64,
-    ) -> Result<ContractId, EthereumError> {
-        // TODO: this should be generated only once
-        abigen!(HashedTimelock, "abi/HashedTimelock.json");
+        timelock: Timelock,
+    ) -> Result<LogHtlcNewFilter, EthereumError> {
+        let contract = HashedTimelock::new(self.eth_contract_address, self.client.clone());
 
-        let contract = HashedTimelock::new(self.eth_contract_address, self.client.clone().into());
-
-        let timelock = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .checked_add(Duration::new(timelock, 0))
-            .unwrap()
-            .as_secs();
+        let timelock = resolve_timelock(&self.client, timelock).await?;
 
         let tx = contract
             .new_contract(receiver, hashlock, timelock.into())
-            // TODO: parameterize the amount to lock
             .value(amount_wei);
 
-        let receipt = tx.send().await.unwrap().await.unwrap();
-
-        // In solidity the first topic is the hash of the signature of the event
-        // So "contractId" will be in second place on the topics of the "LogHTLCNew" event
-        let contract_id: H256 = receipt.unwrap().logs[0].topics[1];
-
-        Ok(contract_id.into())
+        let receipt = send_transaction(tx).await?;
+        find_event::<LogHtlcNewFilter>(&receipt, self.eth_contract_address)
     }
 
     pub async fn withdraw(
@@ -87,45 +105,106 @@ impl EthereumContractManager {
         contract_id: ContractId,
         preimage: Preimage,
     ) -> Result<(), EthereumError> {
-        // TODO: this should be generated only once
-        abigen!(HashedTimelock, "abi/HashedTimelock.json");
-
-        let contract = HashedTimelock::new(self.eth_contract_address, self.client.clone().into());
+        let contract = HashedTimelock::new(self.eth_contract_address, self.client.clone());
 
         let tx = contract.withdraw(contract_id, preimage);
-        tx.send().await.unwrap().await.unwrap();
+        send_transaction(tx).await?;
 
         Ok(())
     }
 
     pub async fn refund(&self, contract_id: ContractId) -> Result<(), EthereumError> {
-        // TODO: this should be generated only once
-        abigen!(HashedTimelock, "abi/HashedTimelock.json");
-
-        let contract = HashedTimelock::new(self.eth_contract_address, self.client.clone().into());
+        let contract = HashedTimelock::new(self.eth_contract_address, self.client.clone());
 
         let tx = contract.refund(contract_id);
-        tx.send().await.unwrap().await.unwrap();
+        send_transaction(tx).await?;
 
         Ok(())
     }
 
-    pub async fn get_preimage(&self, contract_id: ContractId) -> Result<Preimage, EthereumError> {
-        // TODO: this should be generated only once
-        abigen!(HashedTimelock, "abi/HashedTimelock.json");
+    // The preimage is only available after the receiver withdraws the funds
+    pub async fn get_preimage(
+        &self,
+        contract_id: ContractId,
+    ) -> Result<Option<Preimage>, EthereumError> {
+        let contract = self.get_contract(contract_id).await?;
+        if !contract.withdrawn {
+            return Ok(None);
+        }
+        Ok(Some(contract.preimage))
+    }
 
-        let contract = HashedTimelock::new(self.eth_contract_address, self.client.clone().into());
+    pub async fn get_contract(
+        &self,
+        contract_id: ContractId,
+    ) -> Result<EthereumContract, EthereumError> {
+        let contract = HashedTimelock::new(self.eth_contract_address, self.client.clone());
 
         // We don't even need to submit a transaction into the network
         // as the "call" operation will result in a state read in the provider
-        let res = contract.get_contract(contract_id).call().await.unwrap();
+        let res = contract
+            .get_contract(contract_id)
+            .call()
+            .await
+            .map_err(call_error)?;
 
-        // In the return type of the "get_contract" solidity method, the "preimage" field has index 7
-        let preimage = res.7;
-        Ok(preimage)
+        // The fields follow the same order as in the return type of the "get_contract" solidity method
+        Ok(EthereumContract {
+            sender: res.0,
+            receiver: res.1,
+            amount: res.2,
+            hashlock: res.3,
+            timelock: res.4,
+            withdrawn: res.5,
+            refunded: res.6,
+            preimage: res.7,
+        })
+    }
+
+    // A contract can be refunded if the funds are still locked and the timelock has passed
+    pub async fn is_refundable(&self, contract_id: ContractId) -> Result<bool, EthereumError> {
+        let contract = self.get_contract(contract_id).await?;
+        if contract.withdrawn || contract.refunded {
+            return Ok(false);
+        }
+
+        let chain_time = self.current_chain_time().await?;
+        Ok(contract.timelock <= chain_time.into())
+    }
+
+    // The smart contract compares the timelocks against the latest block timestamp, not our local clock
+    pub async fn current_chain_time(&self) -> Result<u64, EthereumError> {
+        get_chain_time(&self.client).await
+    }
+
+    // The same balance also pays for the fees of our transactions
+    pub async fn balance(&self) -> Result<U256, EthereumError> {
+        self.client
+            .get_balance(wallet_address(&self.client), None)
+            .await
+            .map_err(provider_error)
     }
 }
 
+// Contract information as stored in the HashedTimelock smart contract
+// If the contract does not exist, all the fields will have their default value
+#[derive(Debug, Clone)]
+pub struct EthereumContract {
+    pub sender: Address,
+    pub receiver: Address,
+    pub amount: U256,
+    pub hashlock: Hashlock,
+    pub timelock: U256,
+    pub withdrawn: bool,
+    pub refunded: bool,
+    pub preimage: Preimage,
+}
+
+// the address of our wallet, the sender of all the transactions of the client
+fn wallet_address(client: &EthereumClient) -> Address {
+    client.inner().address()
+}
+
 fn parse_rpc_url(input: String) -> Result<Provider<Http>, EthereumError> {
     Provider::<Http>::try_from(input.clone()).map_err(|e| EthereumError::InvalidRpcUrl {
         input,
@@ -133,6 +212,134 @@ fn parse_rpc_url(input: String) -> Result<Provider<Http>, EthereumError> {
     })
 }
 
+async fn get_chain_time(client: &EthereumClient) -> Result<u64, EthereumError> {
+    let block = client
+        .get_block(BlockNumber::Latest)
+        .await
+        .map_err(provider_error)?
+        .ok_or_else(|| provider_error("Latest block not found"))?;
+
+    Ok(block.timestamp.as_u64())
+}
+
+// Decodes the event emitted by the given contract, ignoring the ones of any other contract (e.g. token transfers)
+fn find_event<E: EthEvent>(
+    receipt: &TransactionReceipt,
+    contract_address: Address,
+) -> Result<E, EthereumError> {
+    receipt
+        .logs
+        .iter()
+        .filter(|log| {
+            log.address == contract_address && log.topics.first() == Some(&E::signature())
+        })
+        .find_map(|log| E::decode_log(&log.clone().into()).ok())
+        .ok_or_else(|| EthereumError::MissingEvent {
+            event: E::name().to_string(),
+            tx_hash: receipt.transaction_hash,
+        })
+}
+
+// Waits until the transaction is mined, failing if it did not succeed
+async fn send_transaction<D: Detokenize>(
+    tx: ContractCall<EthereumClient, D>,
+) -> Result<TransactionReceipt, EthereumError> {
+    let pending_tx = tx.send().await.map_err(send_error)?;
+    let tx_hash = pending_tx.tx_hash();
+
+    let receipt = tokio::time::timeout(RECEIPT_TIMEOUT, pending_tx)
+        .await
+        .map_err(|_| EthereumError::MissingReceipt {
+            tx_hash,
+            detail: format!("not mined after {}s", RECEIPT_TIMEOUT.as_secs()),
+        })?
+        .map_err(|e| EthereumError::MissingReceipt {
+            tx_hash,
+            detail: e.to_string(),
+        })?
+        .ok_or(EthereumError::TransactionDropped { tx_hash })?;
+
+    // A mined transaction can still fail, the reason is not included in the receipt
+    if receipt.status == Some(U64::zero()) {
+        return Err(EthereumError::TransactionReverted {
+            tx_hash: Some(tx_hash),
+            reason: revert_reason(tx, &receipt).await,
+        });
+    }
+
+    Ok(receipt)
+}
+
+// Replays the reverted transaction as a call in the block where it was mined, which reverts again with the reason
+async fn revert_reason<D: Detokenize>(
+    tx: ContractCall<EthereumClient, D>,
+    receipt: &TransactionReceipt,
+) -> Option<String> {
+    let block_number = receipt.block_number?;
+    match tx.block(BlockNumber::Number(block_number)).call().await {
+        Err(e) => e.decode_revert::<String>(),
+        // the state of the block is not the one that the transaction found, so it may not revert anymore
+        Ok(_) => None,
+    }
+}
+
+// We use the chain time instead of our local clock, so clock drifts do not change the refund window
+async fn resolve_timelock(
+    client: &EthereumClient,
+    timelock: Timelock,
+) -> Result<u64, EthereumError> {
+    let chain_time = get_chain_time(client).await?;
+    absolute_timelock(timelock, chain_time)
+}
+
+fn absolute_timelock(timelock: Timelock, chain_time: u64) -> Result<u64, EthereumError> {
+    let timelock = match timelock {
+        Timelock::Absolute(timelock) => timelock,
+        Timelock::Relative(secs) => chain_time
+            .checked_add(secs)
+            .ok_or(EthereumError::InvalidTimelock { timelock: secs })?,
+    };
+
+    // The smart contracts reject the timelocks that are not in the future
+    if timelock <= chain_time {
+        return Err(EthereumError::ExpiredTimelock {
+            timelock,
+            chain_time,
+        });
+    }
+
+    Ok(timelock)
+}
+
+// The provider simulates the transactions before sending them, so most reverts are detected here
+fn send_error(e: ContractError<EthereumClient>) -> EthereumError {
+    if e.is_revert() {
+        return EthereumError::TransactionReverted {
+            tx_hash: None,
+            reason: e.decode_revert::<String>(),
+        };
+    }
+    EthereumError::SendError {
+        detail: e.to_string(),
+    }
+}
+
+fn call_error(e: ContractError<EthereumClient>) -> EthereumError {
+    if e.is_revert() {
+        return EthereumError::TransactionReverted {
+            tx_hash: None,
+            reason: e.decode_revert::<String>(),
+        };
+    }
+    provider_error(e)
+}
+
+fn provider_error(e: impl ToString) -> EthereumError {
+    EthereumError::ProviderError {
+        detail: e.to_string(),
+    }
+}
+
 fn parse_address(input: String) -> Result<Address, EthereumError> {
     input
         .parse::<Address>()
@@ -150,4 +357,157 @@ pub enum EthereumError {
     InvalidRpcUrl { input: String, detail: String },
     #[error("WalletError: {detail}")]
     WalletError { detail: String },
+    #[error("Provider error: {detail}")]
+    ProviderError { detail: String },
+    #[error("Could not send the transaction: {detail}")]
+    SendError { detail: String },
+    #[error("Transaction reverted: {}", .reason.as_deref().unwrap_or("no reason given"))]
+    TransactionReverted {
+        tx_hash: Option<H256>,
+        reason: Option<String>,
+    },
+    #[error("Could not get the receipt of transaction {tx_hash:?}: {detail}")]
+    MissingReceipt { tx_hash: H256, detail: String },
+    #[error("Transaction {tx_hash:?} was dropped from the mempool")]
+    TransactionDropped { tx_hash: H256 },
+    #[error("Event '{event}' not found in transaction {tx_hash:?}")]
+    MissingEvent { event: String, tx_hash: H256 },
+    #[error("Invalid timelock {timelock}")]
+    InvalidTimelock { timelock: u64 },
+    #[error("Timelock {timelock} is not after the current chain time {chain_time}")]
+    ExpiredTimelock { timelock: u64, chain_time: u64 },
+    #[error("Insufficient balance of token {token:?}, needed {amount} but found {balance}")]
+    InsufficientBalance {
+        token: Address,
+        balance: U256,
+        amount: U256,
+    },
+    #[error("Token {token_id} of {token:?} is owned by {owner:?}")]
+    NotTokenOwner {
+        token: Address,
+        token_id: U256,
+        owner: Address,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::{encode, Token};
+    use ethers::types::Log;
+    use ethers::utils::keccak256;
+
+    fn htlc_address() -> Address {
+        Address::repeat_byte(0xaa)
+    }
+
+    fn new_contract_log(address: Address, contract_id: H256) -> Log {
+        Log {
+            address,
+            topics: vec![
+                LogHtlcNewFilter::signature(),
+                contract_id,
+                H256::from(Address::repeat_byte(0x11)),
+                H256::from(Address::repeat_byte(0x22)),
+            ],
+            data: encode(&[
+                Token::Uint(U256::from(100)),
+                Token::FixedBytes(vec![0x33; 32]),
+                Token::Uint(U256::from(1_700_000_000u64)),
+            ])
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    // emitted by the token before our event when locking ERC20 tokens
+    fn transfer_log() -> Log {
+        Log {
+            address: Address::repeat_byte(0x44),
+            topics: vec![
+                H256::from(keccak256("Transfer(address,address,uint256)")),
+                H256::from(Address::repeat_byte(0x11)),
+                H256::from(htlc_address()),
+            ],
+            data: encode(&[Token::Uint(U256::from(100))]).into(),
+            ..Default::default()
+        }
+    }
+
+    fn receipt_with(logs: Vec<Log>) -> TransactionReceipt {
+        TransactionReceipt {
+            logs,
+            ..Default::default()
+        }
+    }
+
+    const CHAIN_TIME: u64 = 1_700_000_000;
+
+    #[test]
+    fn it_only_accepts_absolute_timelocks_after_the_chain_time() {
+        let err = absolute_timelock(Timelock::Absolute(CHAIN_TIME - 1), CHAIN_TIME).unwrap_err();
+        assert!(matches!(err, EthereumError::ExpiredTimelock { .. }));
+        // the smart contracts reject the timelocks equal to the block timestamp too
+        let err = absolute_timelock(Timelock::Absolute(CHAIN_TIME), CHAIN_TIME).unwrap_err();
+        assert!(matches!(err, EthereumError::ExpiredTimelock { .. }));
+
+        let timelock = absolute_timelock(Timelock::Absolute(CHAIN_TIME + 1), CHAIN_TIME).unwrap();
+        assert_eq!(timelock, CHAIN_TIME + 1);
+    }
+
+    #[test]
+    fn it_adds_relative_timelocks_to_the_chain_time() {
+        let timelock = absolute_timelock(Timelock::Relative(3600), CHAIN_TIME).unwrap();
+        assert_eq!(timelock, CHAIN_TIME + 3600);
+
+        let err = absolute_timelock(Timelock::Relative(0), CHAIN_TIME).unwrap_err();
+        assert!(matches!(err, EthereumError::ExpiredTimelock { .. }));
+    }
+
+    #[test]
+    fn it_rejects_relative_timelocks_beyond_the_u64_bound() {
+        let max_secs = u64::MAX - CHAIN_TIME;
+        let timelock = absolute_timelock(Timelock::Relative(max_secs), CHAIN_TIME).unwrap();
+        assert_eq!(timelock, u64::MAX);
+
+        let err = absolute_timelock(Timelock::Relative(max_secs + 1), CHAIN_TIME).unwrap_err();
+        assert!(matches!(err, EthereumError::InvalidTimelock { .. }));
+    }
+
+    #[test]
+    fn it_skips_the_events_of_the_token() {
+        let contract_id = H256::repeat_byte(0x01);
+        let receipt = receipt_with(vec![
+            transfer_log(),
+            new_contract_log(htlc_address(), contract_id),
+        ]);
+
+        let event = find_event::<LogHtlcNewFilter>(&receipt, htlc_address()).unwrap();
+        assert_eq!(event.contract_id, contract_id.0);
+        assert_eq!(event.amount, U256::from(100));
+        assert_eq!(event.timelock, U256::from(1_700_000_000u64));
+    }
+
+    #[test]
+    fn it_skips_the_same_event_of_other_contracts() {
+        let contract_id = H256::repeat_byte(0x01);
+        let foreign_log = new_contract_log(Address::repeat_byte(0xbb), H256::repeat_byte(0x02));
+        let receipt = receipt_with(vec![
+            foreign_log.clone(),
+            new_contract_log(htlc_address(), contract_id),
+        ]);
+
+        let event = find_event::<LogHtlcNewFilter>(&receipt, htlc_address()).unwrap();
+        assert_eq!(event.contract_id, contract_id.0);
+
+        let receipt = receipt_with(vec![foreign_log]);
+        assert!(find_event::<LogHtlcNewFilter>(&receipt, htlc_address()).is_err());
+    }
+
+    #[test]
+    fn it_fails_without_the_event() {
+        let receipt = receipt_with(vec![transfer_log()]);
+        let err = find_event::<LogHtlcNewFilter>(&receipt, htlc_address()).unwrap_err();
+        assert!(matches!(err, EthereumError::MissingEvent { .. }));
+    }
 }
@@ -0,0 +1,154 @@
+#[cfg(feature = "rand")]
+use rand::rngs::OsRng;
+#[cfg(feature = "rand")]
+use rand::RngCore;
+use ripemd::Ripemd160;
+use sha2::Digest;
+use sha2::Sha256;
+use sha3::Keccak256;
+
+type ByteArray32 = [u8; 32];
+pub type Preimage = ByteArray32;
+pub type Hashlock = ByteArray32;
+
+// The hash functions used by the HTLCs of the different chains to lock the funds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum HashFunction {
+    // used by our own contracts in Ethereum and Tari
+    #[default]
+    Sha256,
+    // the native hash function of the EVM
+    Keccak256,
+    // "HASH160" in Bitcoin scripts
+    Ripemd160Sha256,
+}
+
+impl HashFunction {
+    // Hashes shorter than 32 bytes are padded with zeros on the right,
+    // the same way Solidity does when converting "bytes20" into "bytes32"
+    pub fn create_hashlock(&self, preimage: &Preimage) -> Hashlock {
+        match self {
+            HashFunction::Sha256 => Sha256::digest(preimage).into(),
+            HashFunction::Keccak256 => Keccak256::digest(preimage).into(),
+            HashFunction::Ripemd160Sha256 => {
+                let hash = Ripemd160::digest(Sha256::digest(preimage));
+                let mut hashlock = [0u8; 32];
+                hashlock[..hash.len()].copy_from_slice(&hash);
+                hashlock
+            }
+        }
+    }
+
+    pub fn verify(&self, preimage: &Preimage, hashlock: &Hashlock) -> bool {
+        self.create_hashlock(preimage) == *hashlock
+    }
+}
+
+// Shortcuts for the hash function of our own contracts
+pub fn create_hashlock(preimage: &Preimage) -> Hashlock {
+    HashFunction::Sha256.create_hashlock(preimage)
+}
+
+pub fn verify_preimage(preimage: &Preimage, hashlock: &Hashlock) -> bool {
+    HashFunction::Sha256.verify(preimage, hashlock)
+}
+
+// The preimage is the only secret of a swap, so it must come from a cryptographically secure source
+#[cfg(feature = "rand")]
+pub fn generate_preimage() -> Preimage {
+    let mut preimage = [0u8; 32];
+    OsRng.fill_bytes(&mut preimage);
+    preimage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex: &str) -> Hashlock {
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn it_hashes_with_sha256() {
+        assert_eq!(
+            HashFunction::Sha256.create_hashlock(&[0u8; 32]),
+            from_hex("66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925")
+        );
+        assert_eq!(
+            HashFunction::Sha256.create_hashlock(&[1u8; 32]),
+            from_hex("72cd6e8422c407fb6d098690f1130b7ded7ec2f7f5e1d30bd9d521f015363793")
+        );
+    }
+
+    #[test]
+    fn it_hashes_with_keccak256() {
+        assert_eq!(
+            HashFunction::Keccak256.create_hashlock(&[0u8; 32]),
+            from_hex("290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563")
+        );
+        assert_eq!(
+            HashFunction::Keccak256.create_hashlock(&[1u8; 32]),
+            from_hex("cebc8882fecbec7fb80d2cf4b312bec018884c2d66667c67a90508214bd8bafc")
+        );
+    }
+
+    #[test]
+    fn it_hashes_with_ripemd160_sha256() {
+        assert_eq!(
+            HashFunction::Ripemd160Sha256.create_hashlock(&[0u8; 32]),
+            from_hex("b8bcb07f6344b42ab04250c86a6e8b75d3fdbbc6000000000000000000000000")
+        );
+        assert_eq!(
+            HashFunction::Ripemd160Sha256.create_hashlock(&[1u8; 32]),
+            from_hex("4b6b2e5444c2639cc0fb7bcea5afba3f3cdce239000000000000000000000000")
+        );
+    }
+
+    #[test]
+    fn it_pads_the_short_hashes_on_the_right() {
+        let hashlock = HashFunction::Ripemd160Sha256.create_hashlock(&[1u8; 32]);
+        let hash = Ripemd160::digest(Sha256::digest([1u8; 32]));
+        assert_eq!(&hashlock[..20], hash.as_slice());
+        assert_eq!(hashlock[20..], [0u8; 12]);
+    }
+
+    #[test]
+    fn it_verifies_the_preimage_with_the_same_hash_function() {
+        let preimage = [1u8; 32];
+        for hash_function in [
+            HashFunction::Sha256,
+            HashFunction::Keccak256,
+            HashFunction::Ripemd160Sha256,
+        ] {
+            let hashlock = hash_function.create_hashlock(&preimage);
+            assert!(hash_function.verify(&preimage, &hashlock));
+            assert!(!hash_function.verify(&[0u8; 32], &hashlock));
+        }
+        let hashlock = HashFunction::Keccak256.create_hashlock(&preimage);
+        assert!(!HashFunction::Sha256.verify(&preimage, &hashlock));
+    }
+
+    #[test]
+    fn it_uses_sha256_in_the_shortcuts() {
+        let preimage = [1u8; 32];
+        let hashlock = create_hashlock(&preimage);
+        assert_eq!(hashlock, HashFunction::Sha256.create_hashlock(&preimage));
+        assert!(verify_preimage(&preimage, &hashlock));
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn it_generates_different_preimages() {
+        assert_ne!(generate_preimage(), generate_preimage());
+    }
+}
@@ -0,0 +1,137 @@
+use async_trait::async_trait;
+use thiserror::Error;
+
+pub use hashlock::{HashFunction, Hashlock, Preimage};
+
+// Each network encodes the contract ids in its own way (e.g. a hex hash in Ethereum or a component address in Tari)
+pub type ContractId = String;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContractStatus {
+    Locked,
+    Withdrawn,
+    Refunded,
+}
+
+// Network agnostic view of a HTLC contract
+// The addresses are encoded in the same way as returned by "HtlcBackend::address"
+#[derive(Debug, Clone)]
+pub struct ContractState {
+    pub sender: String,
+    pub receiver: String,
+    pub amount: u64,
+    // the confidential funds are not included in the amount, see "HtlcBackend::verify_confidential_amount"
+    pub confidential: bool,
+    pub hashlock: Hashlock,
+    // in the same units as "HtlcBackend::current_chain_time"
+    pub timelock: u64,
+    pub status: ContractStatus,
+    pub preimage: Option<Preimage>,
+}
+
+// Operations that a network must support to take part in atomic swaps
+#[async_trait]
+pub trait HtlcBackend: Send + Sync {
+    // our own address in the network, as it appears on the contracts
+    fn address(&self) -> String;
+
+    // the hash function that the contracts use to check the preimages against the hashlocks
+    fn hash_function(&self) -> HashFunction;
+
+    // locks our funds, the timelock is absolute and in the same units as "current_chain_time"
+    async fn lock(
+        &mut self,
+        amount: u64,
+        receiver: &str,
+        hashlock: Hashlock,
+        timelock: u64,
+    ) -> Result<ContractId, HtlcError>;
+
+    // the contract created by a previous "lock" with the same arguments, if the network already confirmed it
+    // so we can recover the locks that we never learned the result of, without locking our funds twice
+    async fn find_lock(
+        &mut self,
+        amount: u64,
+        receiver: &str,
+        hashlock: Hashlock,
+        timelock: u64,
+    ) -> Result<Option<ContractId>, HtlcError>;
+
+    async fn withdraw(
+        &mut self,
+        contract_id: &ContractId,
+        preimage: Preimage,
+    ) -> Result<(), HtlcError>;
+
+    async fn refund(&mut self, contract_id: &ContractId) -> Result<(), HtlcError>;
+
+    async fn get_contract_state(
+        &mut self,
+        contract_id: &ContractId,
+    ) -> Result<ContractState, HtlcError>;
+
+    // only the receiver of a contract with confidential funds can check that they add up to the amount
+    // the networks without confidential funds never report such contracts
+    async fn verify_confidential_amount(
+        &mut self,
+        _contract_id: &ContractId,
+        _amount: u64,
+    ) -> Result<bool, HtlcError> {
+        Ok(false)
+    }
+
+    // the preimage is only available after the receiver withdraws the funds
+    async fn get_preimage(
+        &mut self,
+        contract_id: &ContractId,
+    ) -> Result<Option<Preimage>, HtlcError>;
+
+    // each network has its own rules to know if the timelock of a contract has passed
+    async fn is_refundable(&mut self, contract_id: &ContractId) -> Result<bool, HtlcError>;
+
+    // our funds of the asset that can still be locked, so the funds already in contracts are not included
+    async fn balance(&mut self) -> Result<u64, HtlcError>;
+
+    // the value (e.g. block timestamp or epoch) that the network uses to check the timelocks
+    async fn current_chain_time(&mut self) -> Result<u64, HtlcError>;
+}
+
+#[derive(Error, Debug)]
+pub enum HtlcError {
+    #[error("Invalid contract id '{0}'")]
+    InvalidContractId(ContractId),
+    #[error("Invalid address '{0}'")]
+    InvalidAddress(String),
+    #[error("Invalid amount {0}")]
+    InvalidAmount(u64),
+    #[error("Contract '{0}' not found")]
+    ContractNotFound(ContractId),
+    #[error("Contract '{0}' was not created by our HTLC code")]
+    UntrustedContract(ContractId),
+    #[error("Invalid asset, expected '{expected}' but found '{found}'")]
+    InvalidAsset { expected: String, found: String },
+    #[error("{network} error: {detail}")]
+    NetworkError { network: String, detail: String },
+    #[error("Could not send the {network} transaction: {detail}")]
+    SendError { network: String, detail: String },
+    #[error("{network} transaction reverted: {reason}")]
+    TransactionReverted { network: String, reason: String },
+    #[error("Could not get the result of {network} transaction '{tx_id}': {detail}")]
+    MissingReceipt {
+        network: String,
+        tx_id: String,
+        detail: String,
+    },
+    #[error("{network} transaction '{tx_id}' was dropped")]
+    TransactionDropped { network: String, tx_id: String },
+}
+
+impl HtlcError {
+    // the transaction was sent but we do not know its result, so it may still be confirmed later
+    pub fn is_unconfirmed(&self) -> bool {
+        matches!(
+            self,
+            HtlcError::MissingReceipt { .. } | HtlcError::TransactionDropped { .. }
+        )
+    }
+}
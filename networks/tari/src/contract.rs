@@ -1,3 +1,7 @@
+use std::time::Duration;
+use std::time::SystemTime;
+
+use serde::de::DeserializeOwned;
 use sha2::Digest;
 use sha2::Sha256;
 use tari_crypto::ristretto::RistrettoPublicKey;
@@ -7,29 +11,243 @@ use tari_engine_types::instruction::Instruction;
 use tari_engine_types::substate::SubstateAddress;
 use tari_template_builtin::ACCOUNT_TEMPLATE_ADDRESS;
 use tari_template_lib::args;
+use tari_template_lib::models::ConfidentialWithdrawProof;
+use tari_template_lib::models::VaultId;
 use tari_template_lib::prelude::Amount;
 use tari_template_lib::prelude::ComponentAddress;
 use tari_template_lib::prelude::NonFungibleAddress;
+use tari_template_lib::prelude::NonFungibleId;
+use tari_template_lib::prelude::ResourceAddress;
 use tari_template_lib::prelude::RistrettoPublicKeyBytes;
 use tari_template_lib::prelude::TemplateAddress;
-use tari_template_lib::prelude::CONFIDENTIAL_TARI_RESOURCE_ADDRESS;
 use tari_template_lib::Hash;
 use tari_transaction::SubstateRequirement;
+use tari_transaction::TransactionId;
 use tari_utilities::ByteArray;
+use tari_wallet_daemon_client::types::AccountsGetBalancesRequest;
+use tari_wallet_daemon_client::types::ConfidentialViewVaultBalanceRequest;
+use tari_wallet_daemon_client::types::ProofsGenerateRequest;
+use tari_wallet_daemon_client::types::TransactionGetResultRequest;
 use tari_wallet_daemon_client::types::TransactionSubmitRequest;
 use tari_wallet_daemon_client::types::TransactionWaitResultRequest;
 use tari_wallet_daemon_client::types::TransactionWaitResultResponse;
+use tari_wallet_daemon_client::ComponentAddressOrName;
+use tari_wallet_daemon_client::ConfidentialProofId;
 use tari_wallet_daemon_client::WalletDaemonClient;
 
+use crate::events::decode_events;
+use crate::events::TariEvent;
 use crate::TariError;
 
-type ByteArray32 = [u8; 32];
-pub type Preimage = ByteArray32;
-pub type Hashlock = ByteArray32;
+pub use hashlock::{Hashlock, Preimage};
+
+// the wallet keeps processing the transactions after we stop waiting, see "TariError::TransactionTimeout"
+const TRANSACTION_TIMEOUT_SECS: u64 = 120;
+
+// Same definition as in the "atomic_swap" template
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TimelockMode {
+    Epoch,
+}
+
+// Same definition as in the "atomic_swap" template
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum HashedTimelockStatus {
+    Locked,
+    Withdrawn,
+    Refunded,
+}
+
+// Same definition as in the "atomic_swap" template, as returned by its "get_state" method
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct HashedTimelockState {
+    pub sender_public_key: RistrettoPublicKeyBytes,
+    pub receiver_public_key: RistrettoPublicKeyBytes,
+    pub resource_address: ResourceAddress,
+    pub amount: Amount,
+    pub confidential: bool,
+    pub non_fungible_ids: Vec<NonFungibleId>,
+    pub hashlock: Hash,
+    pub timelock: u64,
+    pub timelock_mode: TimelockMode,
+    pub preimage: Option<Preimage>,
+    pub status: HashedTimelockStatus,
+}
+
+// Same derivation as in the "atomic_swap" template, only its components can have these addresses
+pub fn swap_component_address(
+    swap_template_address: &TemplateAddress,
+    sender_public_key: &RistrettoPublicKeyBytes,
+    hashlock: &Hashlock,
+) -> ComponentAddress {
+    let digest = Sha256::new()
+        .chain_update(b"atomic_swap.component_id")
+        .chain_update(sender_public_key.as_bytes())
+        .chain_update(hashlock)
+        .finalize();
+    let component_id = Hash::from(<[u8; 32]>::from(digest));
+    new_component_address_from_parts(swap_template_address, &component_id)
+}
+
+// The funds to lock in a swap contract, withdrawn from the wallet account
+#[derive(Debug, Clone)]
+pub enum TariAsset {
+    Fungible {
+        resource_address: ResourceAddress,
+        amount: Amount,
+    },
+    NonFungible {
+        resource_address: ResourceAddress,
+        id: NonFungibleId,
+    },
+    // the amount stays hidden, the proof must be generated by the wallet (see "create_confidential_proof")
+    Confidential {
+        resource_address: ResourceAddress,
+        proof_id: ConfidentialProofId,
+        proof: ConfidentialWithdrawProof,
+    },
+}
+
+impl TariAsset {
+    pub fn resource_address(&self) -> ResourceAddress {
+        match self {
+            TariAsset::Fungible {
+                resource_address, ..
+            }
+            | TariAsset::NonFungible {
+                resource_address, ..
+            }
+            | TariAsset::Confidential {
+                resource_address, ..
+            } => *resource_address,
+        }
+    }
+
+    fn withdraw_instruction(&self, account: ComponentAddress) -> Instruction {
+        match self {
+            TariAsset::Fungible {
+                resource_address,
+                amount,
+            } => Instruction::CallMethod {
+                component_address: account,
+                method: "withdraw".to_string(),
+                args: args![*resource_address, *amount],
+            },
+            TariAsset::NonFungible {
+                resource_address,
+                id,
+            } => Instruction::CallMethod {
+                component_address: account,
+                method: "withdraw_non_fungible".to_string(),
+                args: args![*resource_address, id.clone()],
+            },
+            TariAsset::Confidential {
+                resource_address,
+                proof,
+                ..
+            } => Instruction::CallMethod {
+                component_address: account,
+                method: "withdraw_confidential".to_string(),
+                args: args![*resource_address, proof.clone()],
+            },
+        }
+    }
+
+    // the wallet locks the outputs spent by a confidential proof until the transaction is finalized
+    fn proof_ids(&self) -> Vec<ConfidentialProofId> {
+        match self {
+            TariAsset::Confidential { proof_id, .. } => vec![*proof_id],
+            _ => vec![],
+        }
+    }
+
+    // the resource (and the token if any) must be declared as inputs of the transaction
+    fn substate_requirements(&self) -> Vec<SubstateRequirement> {
+        let resource_address = self.resource_address();
+        let mut requirements = vec![SubstateRequirement::new(
+            SubstateAddress::Resource(resource_address),
+            None,
+        )];
+        if let TariAsset::NonFungible { id, .. } = self {
+            requirements.push(SubstateRequirement::new(
+                SubstateAddress::NonFungible(NonFungibleAddress::new(resource_address, id.clone())),
+                None,
+            ));
+        }
+        requirements
+    }
+}
+
+// Contract information as stored in a component of the HashedTimelock template
+#[derive(Debug, Clone)]
+pub struct TariContract {
+    pub sender_public_key: RistrettoPublicKey,
+    pub receiver_public_key: RistrettoPublicKey,
+    pub resource_address: ResourceAddress,
+    // for non-fungible resources, the amount is the number of locked tokens
+    // and for confidential funds it only includes the revealed part (see "get_confidential_balance")
+    pub amount: Amount,
+    pub confidential: bool,
+    pub non_fungible_ids: Vec<NonFungibleId>,
+    pub hashlock: Hashlock,
+    // in epochs, the only time exposed by the engine
+    pub timelock: u64,
+    pub preimage: Option<Preimage>,
+    pub status: HashedTimelockStatus,
+}
+
+// any component can return a state with the same layout, so the keys may not even be valid
+impl TryFrom<HashedTimelockState> for TariContract {
+    type Error = TariError;
+
+    fn try_from(state: HashedTimelockState) -> Result<Self, Self::Error> {
+        Ok(Self {
+            sender_public_key: parse_public_key(&state.sender_public_key)?,
+            receiver_public_key: parse_public_key(&state.receiver_public_key)?,
+            resource_address: state.resource_address,
+            amount: state.amount,
+            confidential: state.confidential,
+            non_fungible_ids: state.non_fungible_ids,
+            hashlock: state.hashlock.into_array(),
+            timelock: state.timelock,
+            preimage: state.preimage,
+            status: state.status,
+        })
+    }
+}
+
+fn parse_public_key(bytes: &RistrettoPublicKeyBytes) -> Result<RistrettoPublicKey, TariError> {
+    RistrettoPublicKey::from_bytes(bytes.as_bytes()).map_err(|e| TariError::InvalidPublicKey {
+        detail: e.to_string(),
+    })
+}
+
+// The output of a readonly call, the components without the called method reject the transaction
+pub fn decode_result<T: DeserializeOwned>(
+    result: &ExecuteResult,
+    method: &str,
+) -> Result<T, TariError> {
+    let invalid_result = |detail: String| TariError::InvalidResult {
+        method: method.to_string(),
+        detail,
+    };
+    let output = match result.finalize.execution_results.first() {
+        Some(output) => output,
+        None => {
+            return Err(invalid_result(match result.finalize.result.reject() {
+                Some(reason) => format!("the transaction was rejected: {}", reason),
+                None => "there are no results".to_string(),
+            }))
+        }
+    };
+    output
+        .decode::<T>()
+        .map_err(|e| invalid_result(e.to_string()))
+}
 
 pub struct TariContractManager {
     client: WalletDaemonClient,
-    wallet_public_key: RistrettoPublicKey,
+    pub(crate) wallet_public_key: RistrettoPublicKey,
     wallet_public_key_index: u64,
     wallet_address: ComponentAddress,
     swap_template_address: TemplateAddress,
@@ -55,16 +273,9 @@ impl TariContractManager {
         })
     }
 
-    // TODO: DRY up with the similar method in the Ethereum crate
-    pub fn create_hashlock(preimage: Preimage) -> Hashlock {
-        let mut hasher = Sha256::new();
-        hasher.update(preimage);
-        hasher.finalize().into()
-    }
-
     pub async fn create_lock_contract(
         &mut self,
-        amount: i64,
+        asset: TariAsset,
         receiver_public_key: RistrettoPublicKey,
         hashlock: Hashlock,
         timelock: u64,
@@ -76,12 +287,7 @@ impl TariContractManager {
             signing_key_index: Some(self.wallet_public_key_index),
             fee_instructions: vec![],
             instructions: vec![
-                Instruction::CallMethod {
-                    component_address: self.wallet_address,
-                    method: "withdraw".to_string(),
-                    // TODO: parameterize the resource to swap
-                    args: args![*CONFIDENTIAL_TARI_RESOURCE_ADDRESS, Amount(amount)],
-                },
+                asset.withdraw_instruction(self.wallet_address),
                 Instruction::PutLastInstructionOutputOnWorkspace {
                     key: b"bucket".to_vec(),
                 },
@@ -97,11 +303,14 @@ impl TariContractManager {
                     ],
                 },
             ],
-            // the inputs are the sender and receiver account addresses
-            inputs: vec![
+            // the inputs are the sender and receiver account addresses, along with the locked asset
+            inputs: [
                 SubstateRequirement::new(SubstateAddress::Component(self.wallet_address), None),
                 SubstateRequirement::new(SubstateAddress::Component(receiver_account), None),
-            ],
+            ]
+            .into_iter()
+            .chain(asset.substate_requirements())
+            .collect(),
             override_inputs: false,
             // we are creating a component with a vault
             new_outputs: 2,
@@ -110,7 +319,7 @@ impl TariContractManager {
             new_non_fungible_outputs: vec![],
             new_non_fungible_index_outputs: vec![],
             is_dry_run: false,
-            proof_ids: vec![],
+            proof_ids: asset.proof_ids(),
         };
 
         let response = self.submit_transaction(request).await?;
@@ -121,6 +330,33 @@ impl TariContractManager {
         Ok(component)
     }
 
+    // The output of the proof is encrypted for the receiver, who can then recover the amount after withdrawing
+    pub async fn create_confidential_proof(
+        &mut self,
+        resource_address: ResourceAddress,
+        amount: Amount,
+        receiver_public_key: RistrettoPublicKey,
+    ) -> Result<TariAsset, TariError> {
+        let response = self
+            .client
+            .create_transfer_proof(ProofsGenerateRequest {
+                amount,
+                reveal_amount: Amount::zero(),
+                account: Some(ComponentAddressOrName::ComponentAddress(
+                    self.wallet_address,
+                )),
+                resource_address,
+                destination_public_key: receiver_public_key,
+            })
+            .await?;
+
+        Ok(TariAsset::Confidential {
+            resource_address,
+            proof_id: response.proof_id,
+            proof: response.proof,
+        })
+    }
+
     pub async fn withdraw(
         &mut self,
         contract: ComponentAddress,
@@ -229,10 +465,274 @@ impl TariContractManager {
         };
 
         let result = self.submit_dry_run_transaction(request).await?;
-        let preimage = result.finalize.execution_results[0]
-            .decode::<Option<Preimage>>()
-            .unwrap();
-        Ok(preimage)
+        decode_result(&result, "get_preimage")
+    }
+
+    // Reads all the information needed to verify a contract in a single readonly call
+    pub async fn get_contract_state(
+        &mut self,
+        contract: ComponentAddress,
+    ) -> Result<HashedTimelockState, TariError> {
+        let request = TransactionSubmitRequest {
+            signing_key_index: Some(self.wallet_public_key_index),
+            fee_instructions: vec![],
+            instructions: vec![Instruction::CallMethod {
+                component_address: contract,
+                method: "get_state".to_string(),
+                args: args![],
+            }],
+            inputs: vec![SubstateRequirement::new(
+                SubstateAddress::Component(contract),
+                None,
+            )],
+            override_inputs: false,
+            new_outputs: 0,
+            specific_non_fungible_outputs: vec![],
+            new_resources: vec![],
+            new_non_fungible_outputs: vec![],
+            new_non_fungible_index_outputs: vec![],
+            // This is a readonly operation
+            is_dry_run: true,
+            proof_ids: vec![],
+        };
+
+        let result = self.submit_dry_run_transaction(request).await?;
+        decode_result(&result, "get_state")
+    }
+
+    pub async fn get_contract(
+        &mut self,
+        contract: ComponentAddress,
+    ) -> Result<TariContract, TariError> {
+        let state = self.get_contract_state(contract).await?;
+        state.try_into()
+    }
+
+    // Any template could mimic the methods of the swap template, so we check where the component comes from
+    pub fn is_swap_contract(&self, contract: ComponentAddress, state: &TariContract) -> bool {
+        let sender_public_key =
+            RistrettoPublicKeyBytes::from_bytes(state.sender_public_key.as_bytes()).unwrap();
+        contract
+            == swap_component_address(
+                &self.swap_template_address,
+                &sender_public_key,
+                &state.hashlock,
+            )
+    }
+
+    // We can only lock once per hashlock, as the address of the contract only depends on both
+    pub fn our_swap_contract_address(&self, hashlock: &Hashlock) -> ComponentAddress {
+        let sender_public_key =
+            RistrettoPublicKeyBytes::from_bytes(self.wallet_public_key.as_bytes()).unwrap();
+        swap_component_address(&self.swap_template_address, &sender_public_key, hashlock)
+    }
+
+    pub async fn get_vault_id(&mut self, contract: ComponentAddress) -> Result<VaultId, TariError> {
+        let request = TransactionSubmitRequest {
+            signing_key_index: Some(self.wallet_public_key_index),
+            fee_instructions: vec![],
+            instructions: vec![Instruction::CallMethod {
+                component_address: contract,
+                method: "get_vault_id".to_string(),
+                args: args![],
+            }],
+            inputs: vec![SubstateRequirement::new(
+                SubstateAddress::Component(contract),
+                None,
+            )],
+            override_inputs: false,
+            new_outputs: 0,
+            specific_non_fungible_outputs: vec![],
+            new_resources: vec![],
+            new_non_fungible_outputs: vec![],
+            new_non_fungible_index_outputs: vec![],
+            // This is a readonly operation
+            is_dry_run: true,
+            proof_ids: vec![],
+        };
+
+        let result = self.submit_dry_run_transaction(request).await?;
+        decode_result(&result, "get_vault_id")
+    }
+
+    // The committed amount of the confidential funds locked in a contract, only visible with the view key of the resource
+    // Returns "None" if any of the outputs is outside the expected range, so its value could not be recovered
+    pub async fn get_confidential_balance(
+        &mut self,
+        contract: ComponentAddress,
+        view_key_id: u64,
+        minimum_expected_value: u64,
+        maximum_expected_value: u64,
+    ) -> Result<Option<u64>, TariError> {
+        let vault_id = self.get_vault_id(contract).await?;
+        let response = self
+            .client
+            .view_vault_balance(ConfidentialViewVaultBalanceRequest {
+                vault_id,
+                minimum_expected_value: Some(minimum_expected_value),
+                maximum_expected_value: Some(maximum_expected_value),
+                view_key_id,
+            })
+            .await?;
+
+        Ok(response.balances.values().copied().sum())
+    }
+
+    pub async fn get_timelock(&mut self, contract: ComponentAddress) -> Result<u64, TariError> {
+        let request = TransactionSubmitRequest {
+            signing_key_index: Some(self.wallet_public_key_index),
+            fee_instructions: vec![],
+            instructions: vec![Instruction::CallMethod {
+                component_address: contract,
+                method: "get_timelock".to_string(),
+                args: args![],
+            }],
+            inputs: vec![SubstateRequirement::new(
+                SubstateAddress::Component(contract),
+                None,
+            )],
+            override_inputs: false,
+            new_outputs: 0,
+            specific_non_fungible_outputs: vec![],
+            new_resources: vec![],
+            new_non_fungible_outputs: vec![],
+            new_non_fungible_index_outputs: vec![],
+            // This is a readonly operation
+            is_dry_run: true,
+            proof_ids: vec![],
+        };
+
+        let result = self.submit_dry_run_transaction(request).await?;
+        decode_result(&result, "get_timelock")
+    }
+
+    // The revealed funds of a resource in our account, for non-fungible resources the number of tokens
+    pub async fn get_balance(
+        &mut self,
+        resource_address: ResourceAddress,
+    ) -> Result<Amount, TariError> {
+        let request = TransactionSubmitRequest {
+            signing_key_index: Some(self.wallet_public_key_index),
+            fee_instructions: vec![],
+            instructions: vec![Instruction::CallMethod {
+                component_address: self.wallet_address,
+                method: "balance".to_string(),
+                args: args![resource_address],
+            }],
+            inputs: vec![SubstateRequirement::new(
+                SubstateAddress::Component(self.wallet_address),
+                None,
+            )],
+            override_inputs: false,
+            new_outputs: 0,
+            specific_non_fungible_outputs: vec![],
+            new_resources: vec![],
+            new_non_fungible_outputs: vec![],
+            new_non_fungible_index_outputs: vec![],
+            // This is a readonly operation
+            is_dry_run: true,
+            proof_ids: vec![],
+        };
+
+        let result = self.submit_dry_run_transaction(request).await?;
+        decode_result(&result, "balance")
+    }
+
+    // The wallet keeps track of our confidential outputs, as their amounts are hidden from the network
+    pub async fn get_confidential_account_balance(
+        &mut self,
+        resource_address: ResourceAddress,
+    ) -> Result<Amount, TariError> {
+        let response = self
+            .client
+            .get_account_balances(AccountsGetBalancesRequest {
+                account: Some(ComponentAddressOrName::ComponentAddress(
+                    self.wallet_address,
+                )),
+                refresh: true,
+            })
+            .await?;
+
+        let balance = response
+            .balances
+            .iter()
+            .filter(|entry| entry.resource_address == resource_address)
+            .map(|entry| entry.confidential_balance)
+            .fold(Amount::zero(), |total, balance| total + balance);
+        Ok(balance)
+    }
+
+    // The epoch that the network uses to check the timelocks of the swap template
+    pub async fn get_current_epoch(&mut self) -> Result<u64, TariError> {
+        let request = TransactionSubmitRequest {
+            signing_key_index: Some(self.wallet_public_key_index),
+            fee_instructions: vec![],
+            instructions: vec![Instruction::CallFunction {
+                template_address: self.swap_template_address,
+                function: "current_epoch".to_string(),
+                args: args![],
+            }],
+            inputs: vec![],
+            override_inputs: false,
+            new_outputs: 0,
+            specific_non_fungible_outputs: vec![],
+            new_resources: vec![],
+            new_non_fungible_outputs: vec![],
+            new_non_fungible_index_outputs: vec![],
+            // This is a readonly operation
+            is_dry_run: true,
+            proof_ids: vec![],
+        };
+
+        let result = self.submit_dry_run_transaction(request).await?;
+        decode_result(&result, "current_epoch")
+    }
+
+    // Tari has no wall-clock time on-chain, so we estimate the epoch of the deadline from the epoch duration
+    // The timelock is rounded up, so the funds cannot be refunded before the deadline
+    pub async fn timelock_for_deadline(
+        &mut self,
+        deadline: SystemTime,
+        epoch_duration: Duration,
+    ) -> Result<u64, TariError> {
+        let current_epoch = self.get_current_epoch().await?;
+        timelock_for_deadline(current_epoch, SystemTime::now(), deadline, epoch_duration)
+    }
+
+    // The estimated wall-clock time after which a timelock will allow refunds
+    pub async fn estimate_deadline(
+        &mut self,
+        timelock: u64,
+        epoch_duration: Duration,
+    ) -> Result<SystemTime, TariError> {
+        let current_epoch = self.get_current_epoch().await?;
+        estimate_deadline(current_epoch, SystemTime::now(), timelock, epoch_duration)
+    }
+
+    // A contract can be refunded if the funds are still locked and the timelock has passed
+    pub async fn is_refundable(&mut self, contract: ComponentAddress) -> Result<bool, TariError> {
+        let state = self.get_contract_state(contract).await?;
+        if state.status != HashedTimelockStatus::Locked {
+            return Ok(false);
+        }
+
+        let current_epoch = self.get_current_epoch().await?;
+        Ok(current_epoch > state.timelock)
+    }
+
+    // The events of our templates emitted by a transaction, empty if the transaction is not finalized yet
+    pub async fn get_transaction_events(
+        &mut self,
+        transaction_id: TransactionId,
+    ) -> Result<Vec<TariEvent>, TariError> {
+        let response = self
+            .client
+            .get_transaction_result(TransactionGetResultRequest { transaction_id })
+            .await?;
+        match response.result {
+            Some(result) => decode_events(&result.events),
+            None => Ok(vec![]),
+        }
     }
 
     async fn submit_transaction(
@@ -244,7 +744,7 @@ impl TariContractManager {
             .client
             .wait_transaction_result(TransactionWaitResultRequest {
                 transaction_id: resp.transaction_id,
-                timeout_secs: None,
+                timeout_secs: Some(TRANSACTION_TIMEOUT_SECS),
             })
             .await?;
         if wait_resp.timed_out {
@@ -259,9 +759,23 @@ impl TariContractManager {
         &mut self,
         request: TransactionSubmitRequest,
     ) -> Result<ExecuteResult, TariError> {
-        let resp = self.client.submit_transaction(&request).await?;
-        let result = resp.result.unwrap();
-        Ok(result)
+        let resp = self
+            .client
+            .submit_transaction(&request)
+            .await
+            .map_err(|e| {
+                // the wallet cannot run calls over the components that do not exist
+                if is_substate_not_found(&e.to_string()) {
+                    TariError::SubstateNotFound {
+                        detail: e.to_string(),
+                    }
+                } else {
+                    e.into()
+                }
+            })?;
+        resp.result.ok_or(TariError::MissingResult {
+            transaction_id: resp.transaction_id,
+        })
     }
 
     fn get_account_address(public_key: &RistrettoPublicKey) -> ComponentAddress {
@@ -275,3 +789,123 @@ impl TariContractManager {
         )
     }
 }
+
+// the wallet only reports the errors as messages
+fn is_substate_not_found(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("substate") && message.contains("not found")
+}
+
+fn timelock_for_deadline(
+    current_epoch: u64,
+    now: SystemTime,
+    deadline: SystemTime,
+    epoch_duration: Duration,
+) -> Result<u64, TariError> {
+    let remaining = deadline
+        .duration_since(now)
+        .map_err(|_| TariError::InvalidDeadline {
+            detail: "the deadline is in the past".to_string(),
+        })?;
+    let epochs = epochs_for_duration(remaining, epoch_duration)?;
+    current_epoch
+        .checked_add(epochs)
+        .ok_or_else(|| TariError::InvalidDeadline {
+            detail: "the deadline is too far in the future".to_string(),
+        })
+}
+
+fn estimate_deadline(
+    current_epoch: u64,
+    now: SystemTime,
+    timelock: u64,
+    epoch_duration: Duration,
+) -> Result<SystemTime, TariError> {
+    let remaining_epochs = timelock.saturating_sub(current_epoch);
+    u32::try_from(remaining_epochs)
+        .ok()
+        .and_then(|epochs| epoch_duration.checked_mul(epochs))
+        .and_then(|remaining| now.checked_add(remaining))
+        .ok_or_else(|| TariError::InvalidDeadline {
+            detail: format!("timelock {} is too far in the future", timelock),
+        })
+}
+
+fn epochs_for_duration(duration: Duration, epoch_duration: Duration) -> Result<u64, TariError> {
+    if epoch_duration.is_zero() {
+        return Err(TariError::InvalidDeadline {
+            detail: "the epoch duration cannot be zero".to_string(),
+        });
+    }
+    let epoch_nanos = epoch_duration.as_nanos();
+    let epochs = (duration.as_nanos() + epoch_nanos - 1) / epoch_nanos;
+    u64::try_from(epochs).map_err(|_| TariError::InvalidDeadline {
+        detail: "the deadline is too far in the future".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPOCH_DURATION: Duration = Duration::from_secs(60);
+
+    fn now() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+    }
+
+    #[test]
+    fn it_only_recognizes_the_missing_substates() {
+        assert!(is_substate_not_found("Substate component_01 not found"));
+        assert!(!is_substate_not_found("Request timed out"));
+        assert!(!is_substate_not_found("Method not found"));
+    }
+
+    #[test]
+    fn it_rounds_the_timelock_up_to_the_next_epoch() {
+        let deadline = now() + Duration::from_secs(90);
+        let timelock = timelock_for_deadline(100, now(), deadline, EPOCH_DURATION).unwrap();
+        assert_eq!(timelock, 102);
+
+        let deadline = now() + Duration::from_secs(120);
+        let timelock = timelock_for_deadline(100, now(), deadline, EPOCH_DURATION).unwrap();
+        assert_eq!(timelock, 102);
+    }
+
+    #[test]
+    fn it_rejects_deadlines_in_the_past() {
+        let deadline = now() - Duration::from_secs(1);
+        assert!(timelock_for_deadline(100, now(), deadline, EPOCH_DURATION).is_err());
+    }
+
+    #[test]
+    fn it_rejects_a_zero_epoch_duration() {
+        let deadline = now() + Duration::from_secs(60);
+        assert!(timelock_for_deadline(100, now(), deadline, Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn it_estimates_the_deadline_of_a_timelock() {
+        let deadline = estimate_deadline(100, now(), 110, EPOCH_DURATION).unwrap();
+        assert_eq!(deadline, now() + Duration::from_secs(600));
+
+        // the timelocks that already passed can be refunded right away
+        let deadline = estimate_deadline(100, now(), 90, EPOCH_DURATION).unwrap();
+        assert_eq!(deadline, now());
+    }
+
+    #[test]
+    fn it_rejects_timelocks_too_far_in_the_future() {
+        assert!(estimate_deadline(0, now(), u64::MAX, EPOCH_DURATION).is_err());
+    }
+
+    #[test]
+    fn the_estimated_deadline_is_never_before_the_requested_one() {
+        for secs in [1, 59, 60, 61, 3599, 3600, 86_401] {
+            let deadline = now() + Duration::from_secs(secs);
+            let timelock = timelock_for_deadline(100, now(), deadline, EPOCH_DURATION).unwrap();
+            let estimated = estimate_deadline(100, now(), timelock, EPOCH_DURATION).unwrap();
+            assert!(estimated >= deadline);
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use std::str::FromStr;
+
+use tari_crypto::ristretto::RistrettoPublicKey;
+use tari_engine_types::events::Event;
+use tari_template_lib::prelude::Amount;
+use tari_template_lib::prelude::ComponentAddress;
+use tari_template_lib::prelude::ResourceAddress;
+use tari_utilities::hex::from_hex;
+use tari_utilities::hex::Hex;
+
+use crate::contract::{Hashlock, Preimage};
+use crate::TariError;
+
+// Topics of the events emitted by our templates
+pub const HTLC_CREATED_TOPIC: &str = "HashedTimelock.Created";
+pub const HTLC_WITHDRAWN_TOPIC: &str = "HashedTimelock.Withdrawn";
+pub const HTLC_REFUNDED_TOPIC: &str = "HashedTimelock.Refunded";
+pub const PROVIDER_REGISTERED_TOPIC: &str = "LiquidityProviderIndex.ProviderRegistered";
+pub const POSITIONS_CHANGED_TOPIC: &str = "LiquidityProviderPosition.PositionsChanged";
+pub const NETWORK_ADDRESS_CHANGED_TOPIC: &str = "LiquidityProviderPosition.NetworkAddressChanged";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TariEvent {
+    // emitted by a template function, so the event is not linked to the new component
+    HtlcCreated {
+        sender_public_key: RistrettoPublicKey,
+        receiver_public_key: RistrettoPublicKey,
+        resource_address: ResourceAddress,
+        // only the revealed funds, so it is zero for confidential locks and their receivers must check the vault
+        amount: Amount,
+        confidential: bool,
+        hashlock: Hashlock,
+        timelock: u64,
+    },
+    HtlcWithdrawn {
+        component_address: ComponentAddress,
+        preimage: Preimage,
+    },
+    HtlcRefunded {
+        component_address: ComponentAddress,
+    },
+    ProviderRegistered {
+        owner_token: String,
+        position_component: ComponentAddress,
+    },
+    PositionsChanged {
+        component_address: ComponentAddress,
+        position_count: usize,
+    },
+    NetworkAddressChanged {
+        component_address: ComponentAddress,
+        network_address: String,
+    },
+}
+
+// Decodes the events of our templates in a transaction result, in the order they were emitted
+pub fn decode_events(events: &[Event]) -> Result<Vec<TariEvent>, TariError> {
+    let mut decoded = vec![];
+    for event in events {
+        if let Some(event) = decode_event(event)? {
+            decoded.push(event);
+        }
+    }
+    Ok(decoded)
+}
+
+// Returns "None" for the events of any other template (e.g. the account ones)
+pub fn decode_event(event: &Event) -> Result<Option<TariEvent>, TariError> {
+    let topic = event.topic();
+    let decoded = match topic.as_str() {
+        HTLC_CREATED_TOPIC => TariEvent::HtlcCreated {
+            sender_public_key: parse_public_key(event, "sender_public_key")?,
+            receiver_public_key: parse_public_key(event, "receiver_public_key")?,
+            resource_address: parse_field(event, "resource_address", |v| {
+                ResourceAddress::from_str(v).map_err(|e| e.to_string())
+            })?,
+            amount: parse_field(event, "amount", |v| {
+                v.parse::<i64>().map(Amount).map_err(|e| e.to_string())
+            })?,
+            confidential: parse_field(event, "confidential", |v| {
+                v.parse::<bool>().map_err(|e| e.to_string())
+            })?,
+            hashlock: parse_field(event, "hashlock", parse_bytes)?,
+            timelock: parse_field(event, "timelock", |v| {
+                v.parse::<u64>().map_err(|e| e.to_string())
+            })?,
+        },
+        HTLC_WITHDRAWN_TOPIC => TariEvent::HtlcWithdrawn {
+            component_address: component_address(event)?,
+            preimage: parse_field(event, "preimage", parse_bytes)?,
+        },
+        HTLC_REFUNDED_TOPIC => TariEvent::HtlcRefunded {
+            component_address: component_address(event)?,
+        },
+        PROVIDER_REGISTERED_TOPIC => TariEvent::ProviderRegistered {
+            owner_token: parse_field(event, "owner_token", |v| Ok(v.to_string()))?,
+            position_component: parse_field(event, "position_component", |v| {
+                ComponentAddress::from_str(v).map_err(|e| e.to_string())
+            })?,
+        },
+        POSITIONS_CHANGED_TOPIC => TariEvent::PositionsChanged {
+            component_address: component_address(event)?,
+            position_count: parse_field(event, "position_count", |v| {
+                v.parse::<usize>().map_err(|e| e.to_string())
+            })?,
+        },
+        NETWORK_ADDRESS_CHANGED_TOPIC => TariEvent::NetworkAddressChanged {
+            component_address: component_address(event)?,
+            network_address: parse_field(event, "network_address", |v| Ok(v.to_string()))?,
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(decoded))
+}
+
+fn parse_field<T>(
+    event: &Event,
+    key: &str,
+    parse: impl FnOnce(&str) -> Result<T, String>,
+) -> Result<T, TariError> {
+    let value = event
+        .get_payload(key)
+        .ok_or_else(|| invalid_event(event, format!("missing field '{}'", key)))?;
+    parse(&value).map_err(|e| invalid_event(event, format!("invalid field '{}': {}", key, e)))
+}
+
+fn parse_public_key(event: &Event, key: &str) -> Result<RistrettoPublicKey, TariError> {
+    parse_field(event, key, |v| {
+        RistrettoPublicKey::from_hex(v).map_err(|e| e.to_string())
+    })
+}
+
+fn parse_bytes(value: &str) -> Result<[u8; 32], String> {
+    from_hex(value)
+        .map_err(|e| e.to_string())?
+        .try_into()
+        .map_err(|_| "expected 32 bytes".to_string())
+}
+
+// the engine sets the component of the events emitted inside component methods
+fn component_address(event: &Event) -> Result<ComponentAddress, TariError> {
+    event
+        .component_address()
+        .ok_or_else(|| invalid_event(event, "missing component address".to_string()))
+}
+
+fn invalid_event(event: &Event, detail: String) -> TariError {
+    TariError::InvalidEvent {
+        topic: event.topic(),
+        detail,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tari_template_lib::constants::CONFIDENTIAL_TARI_RESOURCE_ADDRESS;
+
+    use tari_utilities::hex::to_hex;
+
+    use super::*;
+
+    // events emitted by template functions, so they are not linked to any component
+    fn event_with(topic: &str, payload: &[(&str, String)]) -> Event {
+        let mut event = Event::new(
+            None,
+            Default::default(),
+            Default::default(),
+            topic.to_string(),
+            Default::default(),
+        );
+        for (key, value) in payload {
+            event.add_payload(key.to_string(), value.clone());
+        }
+        event
+    }
+
+    fn created_payload(hashlock: String) -> Vec<(&'static str, String)> {
+        let public_key = RistrettoPublicKey::default().to_hex();
+        vec![
+            ("sender_public_key", public_key.clone()),
+            ("receiver_public_key", public_key),
+            (
+                "resource_address",
+                CONFIDENTIAL_TARI_RESOURCE_ADDRESS.to_string(),
+            ),
+            ("amount", "100".to_string()),
+            ("confidential", "false".to_string()),
+            ("hashlock", hashlock),
+            ("timelock", "42".to_string()),
+        ]
+    }
+
+    fn assert_invalid(event: &Event, expected_detail: &str) {
+        match decode_event(event) {
+            Err(TariError::InvalidEvent { topic, detail }) => {
+                assert_eq!(topic, event.topic());
+                assert!(
+                    detail.contains(expected_detail),
+                    "unexpected detail: {}",
+                    detail
+                );
+            }
+            other => panic!("expected an invalid event, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_decodes_a_created_event() {
+        let event = event_with(HTLC_CREATED_TOPIC, &created_payload(to_hex(&[7u8; 32])));
+        match decode_event(&event).unwrap() {
+            Some(TariEvent::HtlcCreated {
+                amount,
+                confidential,
+                hashlock,
+                timelock,
+                ..
+            }) => {
+                assert_eq!(amount, Amount(100));
+                assert!(!confidential);
+                assert_eq!(hashlock, [7u8; 32]);
+                assert_eq!(timelock, 42);
+            }
+            other => panic!("expected a created event, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_ignores_unknown_topics() {
+        let events = vec![
+            event_with("Account.Deposit", &[("amount", "100".to_string())]),
+            event_with("HashedTimelock.Unknown", &[]),
+        ];
+        assert_eq!(decode_events(&events).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn it_rejects_a_missing_payload_key() {
+        let mut payload = created_payload(to_hex(&[7u8; 32]));
+        payload.retain(|(key, _)| *key != "timelock");
+        assert_invalid(
+            &event_with(HTLC_CREATED_TOPIC, &payload),
+            "missing field 'timelock'",
+        );
+
+        let event = event_with(
+            PROVIDER_REGISTERED_TOPIC,
+            &[("owner_token", "token".to_string())],
+        );
+        assert_invalid(&event, "missing field 'position_component'");
+    }
+
+    #[test]
+    fn it_rejects_a_wrong_length_hashlock() {
+        for hashlock in [to_hex(&[7u8; 31]), to_hex(&[7u8; 33]), String::new()] {
+            let event = event_with(HTLC_CREATED_TOPIC, &created_payload(hashlock));
+            assert_invalid(&event, "invalid field 'hashlock'");
+        }
+
+        let event = event_with(HTLC_CREATED_TOPIC, &created_payload("not hex".to_string()));
+        assert_invalid(&event, "invalid field 'hashlock'");
+    }
+
+    #[test]
+    fn it_rejects_a_wrong_length_preimage() {
+        assert!(parse_bytes(&to_hex(&[7u8; 31])).is_err());
+        assert!(parse_bytes(&to_hex(&[7u8; 33])).is_err());
+        assert!(parse_bytes("zz").is_err());
+        assert_eq!(parse_bytes(&to_hex(&[7u8; 32])).unwrap(), [7u8; 32]);
+    }
+
+    #[test]
+    fn it_rejects_component_events_without_a_component() {
+        let event = event_with(HTLC_WITHDRAWN_TOPIC, &[("preimage", to_hex(&[7u8; 32]))]);
+        assert_invalid(&event, "missing component address");
+
+        let event = event_with(HTLC_REFUNDED_TOPIC, &[]);
+        assert_invalid(&event, "missing component address");
+
+        let events = vec![event_with(
+            POSITIONS_CHANGED_TOPIC,
+            &[("position_count", "1".to_string())],
+        )];
+        assert!(decode_events(&events).is_err());
+    }
+
+    #[test]
+    fn it_rejects_unparseable_values() {
+        let mut payload = created_payload(to_hex(&[7u8; 32]));
+        payload[0].1 = "not a key".to_string();
+        assert_invalid(
+            &event_with(HTLC_CREATED_TOPIC, &payload),
+            "invalid field 'sender_public_key'",
+        );
+
+        let mut payload = created_payload(to_hex(&[7u8; 32]));
+        payload[4].1 = "maybe".to_string();
+        assert_invalid(
+            &event_with(HTLC_CREATED_TOPIC, &payload),
+            "invalid field 'confidential'",
+        );
+    }
+}
@@ -0,0 +1,368 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use htlc::ContractId;
+use htlc::ContractState;
+use htlc::ContractStatus;
+use htlc::HashFunction;
+use htlc::Hashlock;
+use htlc::HtlcBackend;
+use htlc::HtlcError;
+use htlc::Preimage;
+use tari_crypto::ristretto::RistrettoPublicKey;
+use tari_template_lib::prelude::Amount;
+use tari_template_lib::prelude::ComponentAddress;
+use tari_template_lib::prelude::NonFungibleId;
+use tari_template_lib::prelude::ResourceAddress;
+use tari_utilities::hex::Hex;
+
+use crate::contract::HashedTimelockStatus;
+use crate::contract::TariAsset;
+use crate::contract::TariContract;
+use crate::contract::TariContractManager;
+use crate::TariError;
+
+// The asset that a backend swaps, as a single wallet can hold many different resources
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TariResource {
+    Fungible(ResourceAddress),
+    NonFungible(ResourceAddress, NonFungibleId),
+    // the funds are locked in confidential outputs, so only the receivers of the contracts can see their amounts
+    // checking the contracts that we receive needs the id of the view key of the resource in our wallet,
+    // without it we can still lock our own funds but never accept the confidential funds of clients
+    Confidential(ResourceAddress, Option<u64>),
+}
+
+impl TariResource {
+    pub fn resource_address(&self) -> ResourceAddress {
+        match self {
+            TariResource::Fungible(resource_address)
+            | TariResource::NonFungible(resource_address, _)
+            | TariResource::Confidential(resource_address, _) => *resource_address,
+        }
+    }
+
+    // the component could be locking any other resource of the network
+    pub fn check_contract(&self, contract: &TariContract) -> Result<(), HtlcError> {
+        if contract.resource_address != self.resource_address() {
+            return Err(HtlcError::InvalidAsset {
+                expected: self.resource_address().to_string(),
+                found: contract.resource_address.to_string(),
+            });
+        }
+
+        // the ids are only reported while the token is still locked
+        if let TariResource::NonFungible(resource_address, id) = self {
+            if contract.status == HashedTimelockStatus::Locked
+                && !contract.non_fungible_ids.contains(id)
+            {
+                return Err(HtlcError::InvalidAsset {
+                    expected: format!("{}/{}", resource_address, id),
+                    found: format!(
+                        "{}/{:?}",
+                        contract.resource_address, contract.non_fungible_ids
+                    ),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Swaps a single resource through the shared swap template, like the Ethereum managers do with their tokens
+pub struct TariHtlcBackend {
+    manager: TariContractManager,
+    resource: TariResource,
+}
+
+impl TariHtlcBackend {
+    pub fn new(manager: TariContractManager, resource: TariResource) -> Self {
+        Self { manager, resource }
+    }
+
+    pub fn resource(&self) -> &TariResource {
+        &self.resource
+    }
+
+    // the clients can send us any component, which fails to return a valid contract state
+    async fn get_contract(
+        &mut self,
+        contract_id: &ContractId,
+        contract_address: ComponentAddress,
+    ) -> Result<TariContract, HtlcError> {
+        match self.manager.get_contract(contract_address).await {
+            Ok(contract) => Ok(contract),
+            Err(TariError::InvalidResult { .. } | TariError::InvalidPublicKey { .. }) => {
+                Err(HtlcError::UntrustedContract(contract_id.clone()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl HtlcBackend for TariHtlcBackend {
+    // in Tari the parties of a contract are identified by their public keys
+    fn address(&self) -> String {
+        self.manager.wallet_public_key.to_hex()
+    }
+
+    // the swap template uses the default hash function of the hashlock crate
+    fn hash_function(&self) -> HashFunction {
+        HashFunction::Sha256
+    }
+
+    async fn lock(
+        &mut self,
+        amount: u64,
+        receiver: &str,
+        hashlock: Hashlock,
+        timelock: u64,
+    ) -> Result<ContractId, HtlcError> {
+        let receiver = RistrettoPublicKey::from_hex(receiver)
+            .map_err(|_| HtlcError::InvalidAddress(receiver.to_string()))?;
+        let asset = match &self.resource {
+            TariResource::Fungible(resource_address) => {
+                let amount: i64 = amount
+                    .try_into()
+                    .map_err(|_| HtlcError::InvalidAmount(amount))?;
+                TariAsset::Fungible {
+                    resource_address: *resource_address,
+                    amount: Amount(amount),
+                }
+            }
+            // a non-fungible token can only be swapped as a whole
+            TariResource::NonFungible(resource_address, id) => {
+                if amount != 1 {
+                    return Err(HtlcError::InvalidAmount(amount));
+                }
+                TariAsset::NonFungible {
+                    resource_address: *resource_address,
+                    id: id.clone(),
+                }
+            }
+            // the output of the proof is encrypted for the receiver, so they can check the amount
+            TariResource::Confidential(resource_address, _) => {
+                let amount: i64 = amount
+                    .try_into()
+                    .map_err(|_| HtlcError::InvalidAmount(amount))?;
+                self.manager
+                    .create_confidential_proof(*resource_address, Amount(amount), receiver.clone())
+                    .await?
+            }
+        };
+
+        let contract = self
+            .manager
+            .create_lock_contract(asset, receiver, hashlock, timelock)
+            .await?;
+        Ok(contract.to_string())
+    }
+
+    async fn find_lock(
+        &mut self,
+        _amount: u64,
+        receiver: &str,
+        hashlock: Hashlock,
+        timelock: u64,
+    ) -> Result<Option<ContractId>, HtlcError> {
+        let contract_address = self.manager.our_swap_contract_address(&hashlock);
+        let contract = self.manager.get_contract(contract_address).await;
+        find_same_lock(contract, contract_address, receiver, timelock)
+    }
+
+    async fn withdraw(
+        &mut self,
+        contract_id: &ContractId,
+        preimage: Preimage,
+    ) -> Result<(), HtlcError> {
+        let contract = parse_contract_id(contract_id)?;
+        self.manager.withdraw(contract, preimage).await?;
+        Ok(())
+    }
+
+    async fn refund(&mut self, contract_id: &ContractId) -> Result<(), HtlcError> {
+        let contract = parse_contract_id(contract_id)?;
+        self.manager.refund(contract).await?;
+        Ok(())
+    }
+
+    async fn get_contract_state(
+        &mut self,
+        contract_id: &ContractId,
+    ) -> Result<ContractState, HtlcError> {
+        let contract_address = parse_contract_id(contract_id)?;
+        let contract = self.get_contract(contract_id, contract_address).await?;
+
+        // otherwise the funds could be locked by a component with the same methods but different rules
+        if !self.manager.is_swap_contract(contract_address, &contract) {
+            return Err(HtlcError::UntrustedContract(contract_id.clone()));
+        }
+
+        self.resource.check_contract(&contract)?;
+
+        let status = match contract.status {
+            HashedTimelockStatus::Locked => ContractStatus::Locked,
+            HashedTimelockStatus::Withdrawn => ContractStatus::Withdrawn,
+            HashedTimelockStatus::Refunded => ContractStatus::Refunded,
+        };
+
+        Ok(ContractState {
+            sender: contract.sender_public_key.to_hex(),
+            receiver: contract.receiver_public_key.to_hex(),
+            amount: contract
+                .amount
+                .value()
+                .try_into()
+                .map_err(|_| HtlcError::NetworkError {
+                    network: "Tari".to_string(),
+                    detail: format!("Invalid amount {}", contract.amount.value()),
+                })?,
+            confidential: contract.confidential,
+            hashlock: contract.hashlock,
+            timelock: contract.timelock,
+            status,
+            preimage: contract.preimage,
+        })
+    }
+
+    // the view key only recovers the amounts of the outputs in the expected range, so we ask for the exact one
+    async fn verify_confidential_amount(
+        &mut self,
+        contract_id: &ContractId,
+        amount: u64,
+    ) -> Result<bool, HtlcError> {
+        let view_key_id = match self.resource {
+            TariResource::Confidential(_, Some(view_key_id)) => view_key_id,
+            _ => {
+                return Err(HtlcError::InvalidAsset {
+                    expected: format!("the revealed funds of {}", self.resource.resource_address()),
+                    found: "confidential funds that we cannot check".to_string(),
+                })
+            }
+        };
+
+        let contract_address = parse_contract_id(contract_id)?;
+        let contract = self.get_contract(contract_id, contract_address).await?;
+        let revealed = u64::try_from(contract.amount.value()).unwrap_or(u64::MAX);
+        let hidden = match amount.checked_sub(revealed) {
+            Some(hidden) => hidden,
+            None => return Ok(false),
+        };
+        let balance = self
+            .manager
+            .get_confidential_balance(contract_address, view_key_id, hidden, hidden)
+            .await?;
+        Ok(balance == Some(hidden))
+    }
+
+    async fn get_preimage(
+        &mut self,
+        contract_id: &ContractId,
+    ) -> Result<Option<Preimage>, HtlcError> {
+        let contract = parse_contract_id(contract_id)?;
+        let preimage = self.manager.get_preimage(contract).await?;
+        Ok(preimage)
+    }
+
+    async fn is_refundable(&mut self, contract_id: &ContractId) -> Result<bool, HtlcError> {
+        let contract = parse_contract_id(contract_id)?;
+        let is_refundable = self.manager.is_refundable(contract).await?;
+        Ok(is_refundable)
+    }
+
+    async fn balance(&mut self) -> Result<u64, HtlcError> {
+        let balance = match self.resource {
+            TariResource::Fungible(resource_address)
+            | TariResource::NonFungible(resource_address, _) => {
+                self.manager.get_balance(resource_address).await?
+            }
+            TariResource::Confidential(resource_address, _) => {
+                self.manager
+                    .get_confidential_account_balance(resource_address)
+                    .await?
+            }
+        };
+        let balance = u64::try_from(balance.value()).unwrap_or_default();
+        match self.resource {
+            // the account only counts the tokens of the resource, so we assume that ours is one of them
+            TariResource::NonFungible(..) => Ok(balance.min(1)),
+            _ => Ok(balance),
+        }
+    }
+
+    // the swap template checks the timelocks against the current epoch
+    async fn current_chain_time(&mut self) -> Result<u64, HtlcError> {
+        let epoch = self.manager.get_current_epoch().await?;
+        Ok(epoch)
+    }
+}
+
+impl From<TariError> for HtlcError {
+    fn from(e: TariError) -> Self {
+        match e {
+            // the wallet keeps processing the transaction after we stop waiting
+            TariError::TransactionTimeout { transaction_id } => HtlcError::MissingReceipt {
+                network: "Tari".to_string(),
+                tx_id: transaction_id.to_string(),
+                detail: "timed out waiting for the result".to_string(),
+            },
+            _ => HtlcError::NetworkError {
+                network: "Tari".to_string(),
+                detail: e.to_string(),
+            },
+        }
+    }
+}
+
+// only a missing component proves that we did not lock, any other error could hide our funds
+fn find_same_lock(
+    contract: Result<TariContract, TariError>,
+    contract_address: ComponentAddress,
+    receiver: &str,
+    timelock: u64,
+) -> Result<Option<ContractId>, HtlcError> {
+    let contract = match contract {
+        Ok(contract) => contract,
+        Err(TariError::SubstateNotFound { .. }) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    // the clients could reuse the hashlock in other swaps, where we could not lock our funds
+    let is_same_lock =
+        contract.receiver_public_key.to_hex() == receiver && contract.timelock == timelock;
+    Ok(is_same_lock.then(|| contract_address.to_string()))
+}
+
+fn parse_contract_id(input: &ContractId) -> Result<ComponentAddress, HtlcError> {
+    ComponentAddress::from_str(input).map_err(|_| HtlcError::InvalidContractId(input.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contract_address() -> ComponentAddress {
+        ComponentAddress::from_str(&format!("component_{}", "01".repeat(32))).unwrap()
+    }
+
+    #[test]
+    fn it_does_not_find_a_lock_in_a_missing_component() {
+        let not_found = Err(TariError::SubstateNotFound {
+            detail: "Substate not found".to_string(),
+        });
+        let found = find_same_lock(not_found, contract_address(), "receiver", 10).unwrap();
+        assert_eq!(found, None);
+    }
+
+    #[test]
+    fn it_fails_when_the_component_cannot_be_read() {
+        // e.g. an empty dry run of an overloaded wallet, our funds could still be locked
+        let error = Err(TariError::InvalidResult {
+            method: "get_state".to_string(),
+            detail: "there are no results".to_string(),
+        });
+        let err = find_same_lock(error, contract_address(), "receiver", 10).unwrap_err();
+        assert!(matches!(err, HtlcError::NetworkError { .. }));
+    }
+}
@@ -4,12 +4,28 @@ use tari_transaction::TransactionId;
 use tari_wallet_daemon_client::error::WalletDaemonClientError;
 
 pub mod contract;
+pub mod events;
+mod htlc_backend;
 pub mod liquidity;
 
+pub use htlc_backend::{TariHtlcBackend, TariResource};
+
 #[derive(Error, Debug)]
 pub enum TariError {
     #[error("Wallet error: {0}")]
     WalletError(#[from] WalletDaemonClientError),
+    #[error("Substate not found: {detail}")]
+    SubstateNotFound { detail: String },
     #[error("Transaction timeout for id: {transaction_id}")]
     TransactionTimeout { transaction_id: TransactionId },
+    #[error("Invalid deadline: {detail}")]
+    InvalidDeadline { detail: String },
+    #[error("Invalid event '{topic}': {detail}")]
+    InvalidEvent { topic: String, detail: String },
+    #[error("Missing result for transaction id: {transaction_id}")]
+    MissingResult { transaction_id: TransactionId },
+    #[error("Invalid result of '{method}': {detail}")]
+    InvalidResult { method: String, detail: String },
+    #[error("Invalid public key: {detail}")]
+    InvalidPublicKey { detail: String },
 }
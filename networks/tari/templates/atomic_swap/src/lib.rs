@@ -1,8 +1,61 @@
-use sha2::Digest;
-use sha2::Sha256;
-use tari_template_lib::{prelude::*, Hash};
+use sha2::{Digest, Sha256};
+use tari_template_lib::{events::emit_event, models::Metadata, prelude::*, Hash};
 
-pub type Preimage = [u8; 32];
+pub use hashlock::Preimage;
+
+// The unit of the timelocks, the engine only exposes the epoch for now
+// Block heights or timestamps can be added as new variants once the engine supports them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TimelockMode {
+    Epoch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum HashedTimelockStatus {
+    Locked,
+    Withdrawn,
+    Refunded,
+}
+
+// All the information needed to verify a contract, so clients can read it in a single call
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct HashedTimelockState {
+    pub sender_public_key: RistrettoPublicKeyBytes,
+    pub receiver_public_key: RistrettoPublicKeyBytes,
+    pub resource_address: ResourceAddress,
+    pub amount: Amount,
+    // the amount only includes the revealed funds, the confidential ones can be checked through the vault
+    pub confidential: bool,
+    pub non_fungible_ids: Vec<NonFungibleId>,
+    pub hashlock: Hash,
+    pub timelock: u64,
+    pub timelock_mode: TimelockMode,
+    pub preimage: Option<Preimage>,
+    pub status: HashedTimelockStatus,
+}
+
+fn public_key_of(token: &NonFungibleAddress, name: &str) -> RistrettoPublicKeyBytes {
+    token
+        .to_public_key()
+        .unwrap_or_else(|| panic!("{} is not a valid public key: {}", name, token))
+}
+
+// The component addresses are derived from the template address and this id, so anyone can check that a contract
+// was created by this template by deriving its address again (a sender cannot reuse a hashlock in two contracts)
+fn component_id(sender_public_key: &RistrettoPublicKeyBytes, hashlock: &Hash) -> Hash {
+    let digest = Sha256::new()
+        .chain_update(b"atomic_swap.component_id")
+        .chain_update(sender_public_key.as_bytes())
+        .chain_update(hashlock.into_array())
+        .finalize();
+    Hash::from(<[u8; 32]>::from(digest))
+}
+
+fn current_time(timelock_mode: TimelockMode) -> u64 {
+    match timelock_mode {
+        TimelockMode::Epoch => Consensus::current_epoch(),
+    }
+}
 
 #[template]
 mod atomic_swap_template {
@@ -10,11 +63,15 @@ mod atomic_swap_template {
 
     pub struct HashedTimelock {
         locked_funds: Vault,
+        confidential: bool,
         sender_token: NonFungibleAddress,
         receiver_token: NonFungibleAddress,
         hashlock: Hash,
         preimage: Option<Preimage>,
-        // TODO: we are using epoch number for now, but we will need block/timestamp support eventually
+        // withdrawals and refunds are final, so funds can only leave the contract once
+        status: HashedTimelockStatus,
+        timelock_mode: TimelockMode,
+        // deadline after which the funds can be refunded, in the units of "timelock_mode"
         timelock: u64,
     }
 
@@ -26,16 +83,18 @@ mod atomic_swap_template {
             hashlock: Hash,
             timelock: u64,
         ) -> HashedTimelockComponent {
-            // funds cannot be empty
+            // funds cannot be empty, confidential funds have no revealed amount but hold commitments
+            let confidential = funds.count_confidential_commitments() > 0;
             assert!(
-                funds.amount() > Amount::zero(),
+                funds.amount() > Amount::zero() || confidential,
                 "The bucket with the funds cannot be empty"
             );
             let locked_funds = Vault::from_bucket(funds);
 
             // check that the timelock is valid
+            let timelock_mode = TimelockMode::Epoch;
             assert!(
-                timelock > Consensus::current_epoch(),
+                timelock > current_time(timelock_mode),
                 "The timelock must be in the future"
             );
 
@@ -49,62 +108,160 @@ mod atomic_swap_template {
             let rules = AccessRules::new()
                 .add_method_rule("withdraw", withdraw_rule)
                 .add_method_rule("refund", refund_rule)
-                .add_method_rule("get_preimage", AccessRule::AllowAll);
+                .add_method_rule("get_sender_public_key", AccessRule::AllowAll)
+                .add_method_rule("get_receiver_public_key", AccessRule::AllowAll)
+                .add_method_rule("get_balance", AccessRule::AllowAll)
+                .add_method_rule("get_hashlock", AccessRule::AllowAll)
+                .add_method_rule("get_preimage", AccessRule::AllowAll)
+                .add_method_rule("get_timelock", AccessRule::AllowAll)
+                .add_method_rule("get_timelock_mode", AccessRule::AllowAll)
+                .add_method_rule("get_resource_address", AccessRule::AllowAll)
+                .add_method_rule("get_non_fungible_ids", AccessRule::AllowAll)
+                .add_method_rule("get_vault_id", AccessRule::AllowAll)
+                .add_method_rule("get_state", AccessRule::AllowAll)
+                .add_method_rule("get_status", AccessRule::AllowAll);
+
+            // the engine only links method events to a component, so watchers match this one by its transaction
+            let sender_public_key = public_key_of(&sender_token, "sender_token");
+            let mut payload = Metadata::new();
+            payload
+                .insert("sender_public_key", sender_public_key.to_string())
+                .insert("receiver_public_key", public_key_of(&receiver_token, "receiver_token").to_string())
+                .insert("resource_address", locked_funds.resource_address().to_string())
+                .insert("amount", locked_funds.balance().to_string())
+                .insert("confidential", confidential.to_string())
+                .insert("hashlock", hashlock.to_string())
+                .insert("timelock", timelock.to_string());
+            emit_event("HashedTimelock.Created", payload);
 
             Self {
                 locked_funds,
+                confidential,
                 sender_token: sender_token.clone(),
                 receiver_token: receiver_token.clone(),
                 hashlock,
+                timelock_mode,
                 timelock,
                 preimage: None,
+                status: HashedTimelockStatus::Locked,
             }
-            .create_with_options(rules, None)
+            .create_with_options(rules, Some(component_id(&sender_public_key, &hashlock)))
         }
 
         // called by the receiver of the swap, once they know the hashlock preimage, to retrieve the funds
         pub fn withdraw(&mut self, preimage: Preimage) -> Bucket {
+            self.check_locked();
             self.check_hashlock(&preimage);
 
             // we explicitly store the preimage to make it easier for the other party to retrieve it
             self.preimage = Some(preimage);
+            self.status = HashedTimelockStatus::Withdrawn;
+
+            let mut payload = Metadata::new();
+            payload.insert("preimage", Hash::from(preimage).to_string());
+            emit_event("HashedTimelock.Withdrawn", payload);
+
             self.locked_funds.withdraw_all()
         }
 
         // called by the sender of the swap to get back the funds if the swap failed
         pub fn refund(&mut self) -> Bucket {
+            self.check_locked();
             self.check_timelock();
 
+            self.status = HashedTimelockStatus::Refunded;
+            emit_event("HashedTimelock.Refunded", Metadata::new());
             self.locked_funds.withdraw_all()
         }
 
         pub fn get_sender_public_key(&self) -> RistrettoPublicKeyBytes {
-            self.sender_token
-                .to_public_key()
-                .unwrap_or_else(|| panic!("sender_token is not a valid public key: {}", self.sender_token))
+            public_key_of(&self.sender_token, "sender_token")
         }
 
         pub fn get_receiver_public_key(&self) -> RistrettoPublicKeyBytes {
-            self.receiver_token
-                .to_public_key()
-                .unwrap_or_else(|| panic!("receiver_token is not a valid public key: {}", self.receiver_token))
+            public_key_of(&self.receiver_token, "receiver_token")
+        }
+
+        // only the revealed funds, the confidential ones can be checked off-chain through the vault
+        pub fn get_balance(&self) -> Amount {
+            self.locked_funds.balance()
+        }
+
+        pub fn get_resource_address(&self) -> ResourceAddress {
+            self.locked_funds.resource_address()
+        }
+
+        // the ids of the locked tokens, always empty for fungible resources
+        pub fn get_non_fungible_ids(&self) -> Vec<NonFungibleId> {
+            if self.locked_funds.resource_type() != ResourceType::NonFungible {
+                return vec![];
+            }
+            self.locked_funds.get_non_fungible_ids()
+        }
+
+        // allows the counterparty to inspect the confidential outputs locked in the contract
+        pub fn get_vault_id(&self) -> VaultId {
+            self.locked_funds.vault_id()
+        }
+
+        pub fn get_hashlock(&self) -> Hash {
+            self.hashlock
         }
 
         pub fn get_preimage(&self) -> Option<Preimage> {
             self.preimage
         }
 
-        fn check_hashlock(&self, preimage: &Preimage) {
-            let mut hasher = Sha256::new();
-            hasher.update(preimage);
-            let hashlock: [u8; 32] = hasher.finalize().into();
-            let hashlock: Hash = hashlock.into();
+        pub fn get_timelock(&self) -> u64 {
+            self.timelock
+        }
+
+        pub fn get_timelock_mode(&self) -> TimelockMode {
+            self.timelock_mode
+        }
+
+        pub fn get_status(&self) -> HashedTimelockStatus {
+            self.status
+        }
 
-            assert!(self.hashlock == hashlock, "Invalid preimage");
+        pub fn get_state(&self) -> HashedTimelockState {
+            HashedTimelockState {
+                sender_public_key: self.get_sender_public_key(),
+                receiver_public_key: self.get_receiver_public_key(),
+                resource_address: self.get_resource_address(),
+                amount: self.get_balance(),
+                confidential: self.confidential,
+                non_fungible_ids: self.get_non_fungible_ids(),
+                hashlock: self.hashlock,
+                timelock: self.timelock,
+                timelock_mode: self.timelock_mode,
+                preimage: self.preimage,
+                status: self.status,
+            }
+        }
+
+        // allows off-chain clients to know the current value used to check the timelocks
+        pub fn current_epoch() -> u64 {
+            Consensus::current_epoch()
+        }
+
+        fn check_locked(&self) {
+            match self.status {
+                HashedTimelockStatus::Locked => {}
+                HashedTimelockStatus::Withdrawn => panic!("The funds were already withdrawn"),
+                HashedTimelockStatus::Refunded => panic!("The funds were already refunded"),
+            }
+        }
+
+        fn check_hashlock(&self, preimage: &Preimage) {
+            assert!(
+                hashlock::verify_preimage(preimage, &self.hashlock.into_array()),
+                "Invalid preimage"
+            );
         }
 
         fn check_timelock(&self) {
-            assert!(Consensus::current_epoch() > self.timelock, "Timelock not yet passed");
+            assert!(current_time(self.timelock_mode) > self.timelock, "Timelock not yet passed");
         }
     }
 }
@@ -1,4 +1,5 @@
 use tari_template_lib::prelude::*;
+use tari_template_lib::{events::emit_event, models::Metadata};
 use tari_template_abi::rust::collections::HashMap;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -43,7 +44,13 @@ mod lp_index_template {
 
             let lp_position_address = TemplateManager::get(self.lp_position_template)
                 .call("new".to_string(), invoke_args![owner_token, network_address]);
-            self.providers.insert(owner_token, lp_position_address);
+            self.providers.insert(owner_token.clone(), lp_position_address);
+
+            let mut payload = Metadata::new();
+            payload
+                .insert("owner_token", owner_token.to_string())
+                .insert("position_component", lp_position_address.to_string());
+            emit_event("LiquidityProviderIndex.ProviderRegistered", payload);
 
             lp_position_address
         }
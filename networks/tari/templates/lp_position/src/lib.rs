@@ -1,4 +1,5 @@
 use tari_template_lib::prelude::*;
+use tari_template_lib::{events::emit_event, models::Metadata};
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Position {
@@ -42,6 +43,10 @@ mod lp_position_template {
 
         pub fn set_network_address(&mut self, new_network_address: String) {
             self.network_address = new_network_address;
+
+            let mut payload = Metadata::new();
+            payload.insert("network_address", self.network_address.clone());
+            emit_event("LiquidityProviderPosition.NetworkAddressChanged", payload);
         }
 
         pub fn get_owner_token(&self) -> NonFungibleAddress {
@@ -54,14 +59,24 @@ mod lp_position_template {
 
         pub fn add_position(&mut self, position: Position) {
             self.positions.push(position);
+            self.emit_positions_changed();
         }
 
         pub fn remove_position(&mut self, index: usize) {
             self.positions.remove(index);
+            self.emit_positions_changed();
         }
 
         pub fn replace_positions(&mut self, positions: Vec<Position>) {
             self.positions = positions;
+            self.emit_positions_changed();
+        }
+
+        // watchers are expected to read the new positions from the component
+        fn emit_positions_changed(&self) {
+            let mut payload = Metadata::new();
+            payload.insert("position_count", self.positions.len().to_string());
+            emit_event("LiquidityProviderPosition.PositionsChanged", payload);
         }
     }
 }
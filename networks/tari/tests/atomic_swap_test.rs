@@ -1,15 +1,31 @@
-use sha2::Digest;
-use sha2::Sha256;
+use htlc::HtlcError;
+use tari::contract::decode_result;
+use tari::contract::swap_component_address;
+use tari::contract::HashedTimelockState;
+use tari::contract::HashedTimelockStatus;
 use tari::contract::Preimage;
+use tari::contract::TariContract;
+use tari::contract::TimelockMode;
+use tari::events::{decode_events, TariEvent};
+use tari::TariError;
+use tari::TariResource;
+use tari_crypto::ristretto::RistrettoPublicKey;
+use tari_engine_types::substate::SubstateAddress;
 use tari_engine_types::virtual_substate::VirtualSubstate;
 use tari_engine_types::virtual_substate::VirtualSubstateAddress;
 use tari_engine_types::{commit_result::ExecuteResult, instruction::Instruction};
 use tari_template_lib::constants::CONFIDENTIAL_TARI_RESOURCE_ADDRESS;
 use tari_template_lib::{
     args,
-    prelude::{Amount, ComponentAddress, NonFungibleAddress, TemplateAddress},
+    args::Arg,
+    prelude::{
+        Amount, ComponentAddress, NonFungibleAddress, NonFungibleId, ResourceAddress,
+        RistrettoPublicKeyBytes, TemplateAddress,
+    },
     Hash,
 };
+use tari_template_test_tooling::support::confidential::generate_confidential_proof;
+use tari_utilities::ByteArray;
 use utils::TemplateTest;
 
 mod utils;
@@ -23,6 +39,7 @@ struct User {
 struct AtomicSwapTest {
     template_test: TemplateTest,
     atomic_swap_template: TemplateAddress,
+    test_tokens_template: TemplateAddress,
     alice: User,
     bob: User,
     preimage: [u8; 32],
@@ -31,11 +48,12 @@ struct AtomicSwapTest {
 }
 
 fn setup() -> AtomicSwapTest {
-    let mut template_test = TemplateTest::new(vec![concat!(
-        env!("CARGO_MANIFEST_DIR"),
-        "/templates/atomic_swap"
-    )]);
+    let mut template_test = TemplateTest::new(vec![
+        concat!(env!("CARGO_MANIFEST_DIR"), "/templates/atomic_swap"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/templates/test_tokens"),
+    ]);
     let atomic_swap_template = template_test.get_template_address("HashedTimelock");
+    let test_tokens_template = template_test.get_template_address("TestTokens");
 
     // Create Alice and Bob accounts
     let (alice_account, alice_token, _) = template_test.create_owned_account();
@@ -52,11 +70,12 @@ fn setup() -> AtomicSwapTest {
     // Default values for the contracts
     let amount = Amount(100);
     let preimage = [0u8; 32];
-    let hashlock = create_hashlock(preimage);
+    let hashlock = hashlock::create_hashlock(&preimage).into();
 
     AtomicSwapTest {
         template_test,
         atomic_swap_template,
+        test_tokens_template,
         alice,
         bob,
         preimage,
@@ -65,50 +84,61 @@ fn setup() -> AtomicSwapTest {
     }
 }
 
-fn create_hashlock(preimage: [u8; 32]) -> Hash {
-    let mut hasher = Sha256::new();
-    hasher.update(preimage);
-    let hashlock: [u8; 32] = hasher.finalize().into();
-    hashlock.into()
-}
-
 fn create_lock_contract(
     test: &mut AtomicSwapTest,
     sender: User,
     receiver: User,
     timelock: u64,
 ) -> ComponentAddress {
-    let result = test
-        .template_test
-        .execute_and_commit(
-            vec![
-                Instruction::CallMethod {
-                    component_address: sender.account_address,
-                    method: "withdraw".to_string(),
-                    args: args![*CONFIDENTIAL_TARI_RESOURCE_ADDRESS, test.amount],
-                },
-                Instruction::PutLastInstructionOutputOnWorkspace {
-                    key: b"bucket".to_vec(),
-                },
-                Instruction::CallFunction {
-                    template_address: test.atomic_swap_template,
-                    function: "create".to_string(),
-                    args: args![
-                        Variable("bucket"),
-                        sender.owner_token,
-                        receiver.owner_token,
-                        test.hashlock,
-                        timelock
-                    ],
-                },
-            ],
-            // Sender proof needed to withdraw
-            vec![sender.owner_token],
-        )
-        .unwrap();
+    let result = try_create_lock_contract(test, sender, receiver, timelock).unwrap();
     result.finalize.execution_results[2].decode().unwrap()
 }
 
+fn try_create_lock_contract(
+    test: &mut AtomicSwapTest,
+    sender: User,
+    receiver: User,
+    timelock: u64,
+) -> Result<ExecuteResult, anyhow::Error> {
+    let withdraw = Instruction::CallMethod {
+        component_address: sender.account_address,
+        method: "withdraw".to_string(),
+        args: args![*CONFIDENTIAL_TARI_RESOURCE_ADDRESS, test.amount],
+    };
+    try_lock_funds(test, withdraw, sender, receiver, timelock)
+}
+
+// locks the bucket returned by the "withdraw" instruction, usually from the sender account
+fn try_lock_funds(
+    test: &mut AtomicSwapTest,
+    withdraw: Instruction,
+    sender: User,
+    receiver: User,
+    timelock: u64,
+) -> Result<ExecuteResult, anyhow::Error> {
+    test.template_test.execute_and_commit(
+        vec![
+            withdraw,
+            Instruction::PutLastInstructionOutputOnWorkspace {
+                key: b"bucket".to_vec(),
+            },
+            Instruction::CallFunction {
+                template_address: test.atomic_swap_template,
+                function: "create".to_string(),
+                args: args![
+                    Variable("bucket"),
+                    sender.owner_token,
+                    receiver.owner_token,
+                    test.hashlock,
+                    timelock
+                ],
+            },
+        ],
+        // Sender proof needed to withdraw
+        vec![sender.owner_token],
+    )
+}
+
 fn withdraw_funds(
     test: &mut AtomicSwapTest,
     contract: ComponentAddress,
@@ -181,6 +211,149 @@ fn get_preimage(
     Ok(preimage)
 }
 
+fn get_state(
+    test: &mut AtomicSwapTest,
+    contract: ComponentAddress,
+) -> Result<HashedTimelockState, anyhow::Error> {
+    let result = test.template_test.execute_and_commit(
+        vec![Instruction::CallMethod {
+            component_address: contract,
+            method: "get_state".to_string(),
+            args: args![],
+        }],
+        vec![],
+    );
+
+    let state = result.unwrap().finalize.execution_results[0]
+        .decode::<HashedTimelockState>()
+        .unwrap();
+    Ok(state)
+}
+
+fn lock_fungible(
+    test: &mut AtomicSwapTest,
+    resource_address: ResourceAddress,
+    sender: User,
+    receiver: User,
+    timelock: u64,
+) -> ComponentAddress {
+    let withdraw = Instruction::CallMethod {
+        component_address: sender.account_address,
+        method: "withdraw".to_string(),
+        args: args![resource_address, test.amount],
+    };
+    let result = try_lock_funds(test, withdraw, sender, receiver, timelock).unwrap();
+    result.finalize.execution_results[2].decode().unwrap()
+}
+
+fn lock_non_fungible(
+    test: &mut AtomicSwapTest,
+    resource_address: ResourceAddress,
+    id: NonFungibleId,
+    sender: User,
+    receiver: User,
+    timelock: u64,
+) -> ComponentAddress {
+    let withdraw = Instruction::CallMethod {
+        component_address: sender.account_address,
+        method: "withdraw_non_fungible".to_string(),
+        args: args![resource_address, id],
+    };
+    let result = try_lock_funds(test, withdraw, sender, receiver, timelock).unwrap();
+    result.finalize.execution_results[2].decode().unwrap()
+}
+
+// mints a new resource with the "TestTokens" template and deposits it in the user account
+fn mint(test: &mut AtomicSwapTest, function: &str, args: Vec<Arg>, user: &User) -> ResourceAddress {
+    let result = test
+        .template_test
+        .execute_and_commit(
+            vec![
+                Instruction::CallFunction {
+                    template_address: test.test_tokens_template,
+                    function: function.to_string(),
+                    args,
+                },
+                Instruction::PutLastInstructionOutputOnWorkspace {
+                    key: b"bucket".to_vec(),
+                },
+                Instruction::CallMethod {
+                    component_address: user.account_address,
+                    method: "deposit".to_string(),
+                    args: args![Variable("bucket")],
+                },
+            ],
+            vec![],
+        )
+        .unwrap();
+
+    let diff = result.finalize.result.accept().unwrap();
+    diff.up_iter()
+        .find_map(|(address, _)| match address {
+            SubstateAddress::Resource(resource_address) => Some(*resource_address),
+            _ => None,
+        })
+        .unwrap()
+}
+
+// the confidential funds are minted directly into the contract, so no withdraw proof is needed
+fn lock_confidential(
+    test: &mut AtomicSwapTest,
+    sender: User,
+    receiver: User,
+    timelock: u64,
+) -> ComponentAddress {
+    let result = try_lock_confidential(test, sender, receiver, timelock).unwrap();
+    result.finalize.execution_results[2].decode().unwrap()
+}
+
+fn try_lock_confidential(
+    test: &mut AtomicSwapTest,
+    sender: User,
+    receiver: User,
+    timelock: u64,
+) -> Result<ExecuteResult, anyhow::Error> {
+    let (proof, _, _) = generate_confidential_proof(test.amount, None);
+    let mint = Instruction::CallFunction {
+        template_address: test.test_tokens_template,
+        function: "mint_confidential".to_string(),
+        args: args![proof],
+    };
+    try_lock_funds(test, mint, sender, receiver, timelock)
+}
+
+fn public_key(user: &User) -> RistrettoPublicKey {
+    let public_key = user.owner_token.to_public_key().unwrap();
+    RistrettoPublicKey::from_bytes(public_key.as_bytes()).unwrap()
+}
+
+fn mint_fungible(test: &mut AtomicSwapTest, supply: Amount, user: &User) -> ResourceAddress {
+    mint(test, "mint_fungible", args![supply], user)
+}
+
+fn mint_non_fungible(test: &mut AtomicSwapTest, id: NonFungibleId, user: &User) -> ResourceAddress {
+    mint(test, "mint_non_fungible", args![id], user)
+}
+
+fn get_balance(
+    test: &mut AtomicSwapTest,
+    user: &User,
+    resource_address: ResourceAddress,
+) -> Amount {
+    let result = test
+        .template_test
+        .execute_and_commit(
+            vec![Instruction::CallMethod {
+                component_address: user.account_address,
+                method: "balance".to_string(),
+                args: args![resource_address],
+            }],
+            vec![],
+        )
+        .unwrap();
+    result.finalize.execution_results[0].decode().unwrap()
+}
+
 // This test simulates an atomic swap between two accounts inside the Tari network.
 // Obviously atomic swaps inside the same network does not have any real world utility,
 // but for testing purposes it's useful as it allow us to verify the Tari atomic swap template
@@ -350,3 +523,436 @@ fn it_does_not_allow_refunds_from_undesignated_users() {
     let err = refund(&mut test, contract_1_component, bob).unwrap_err();
     assert!(err.to_string().contains("Access Denied"));
 }
+
+#[test]
+fn it_reports_the_contract_state() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+    let preimage = test.preimage;
+
+    let timelock = 10u64;
+    let contract = create_lock_contract(&mut test, alice, bob.clone(), timelock);
+
+    let state = get_state(&mut test, contract).unwrap();
+    assert_eq!(state.status, HashedTimelockStatus::Locked);
+    assert_eq!(state.resource_address, *CONFIDENTIAL_TARI_RESOURCE_ADDRESS);
+    assert_eq!(state.amount, test.amount);
+    assert_eq!(state.hashlock, test.hashlock);
+    assert_eq!(state.timelock, timelock);
+    assert_eq!(state.timelock_mode, TimelockMode::Epoch);
+    assert_eq!(state.preimage, None);
+    let result = test
+        .template_test
+        .execute_and_commit(
+            vec![Instruction::CallMethod {
+                component_address: contract,
+                method: "get_timelock_mode".to_string(),
+                args: args![],
+            }],
+            vec![],
+        )
+        .unwrap();
+    let timelock_mode: TimelockMode = result.finalize.execution_results[0].decode().unwrap();
+    assert_eq!(timelock_mode, TimelockMode::Epoch);
+
+    // the preimage is revealed with the withdrawal
+    withdraw_funds(&mut test, contract, preimage, bob).unwrap();
+    let state = get_state(&mut test, contract).unwrap();
+    assert_eq!(state.status, HashedTimelockStatus::Withdrawn);
+    assert_eq!(state.amount, Amount::zero());
+    assert_eq!(state.preimage, Some(preimage));
+}
+
+#[test]
+fn it_does_not_allow_withdrawals_after_refunds() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+    let preimage = test.preimage;
+
+    let timelock = 10u64;
+    let contract = create_lock_contract(&mut test, alice.clone(), bob.clone(), timelock);
+
+    // Alice gets her funds back after the timelock
+    test.template_test.set_virtual_substate(
+        VirtualSubstateAddress::CurrentEpoch,
+        VirtualSubstate::CurrentEpoch(timelock + 1),
+    );
+    refund(&mut test, contract, alice).unwrap();
+
+    // Bob cannot withdraw from the refunded contract, even with a valid preimage
+    let err = withdraw_funds(&mut test, contract, preimage, bob).unwrap_err();
+    assert!(err.to_string().contains("The funds were already refunded"));
+    let state = get_state(&mut test, contract).unwrap();
+    assert_eq!(state.status, HashedTimelockStatus::Refunded);
+    assert_eq!(state.preimage, None);
+}
+
+#[test]
+fn it_does_not_allow_refunds_after_withdrawals() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+    let preimage = test.preimage;
+
+    let timelock = 10u64;
+    let contract = create_lock_contract(&mut test, alice.clone(), bob.clone(), timelock);
+    withdraw_funds(&mut test, contract, preimage, bob).unwrap();
+
+    // Alice cannot refund the withdrawn contract, even after the timelock
+    test.template_test.set_virtual_substate(
+        VirtualSubstateAddress::CurrentEpoch,
+        VirtualSubstate::CurrentEpoch(timelock + 1),
+    );
+    let err = refund(&mut test, contract, alice).unwrap_err();
+    assert!(err.to_string().contains("The funds were already withdrawn"));
+}
+
+#[test]
+fn it_does_not_allow_double_withdrawals() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+    let preimage = test.preimage;
+
+    let timelock = 10u64;
+    let contract = create_lock_contract(&mut test, alice, bob.clone(), timelock);
+    withdraw_funds(&mut test, contract, preimage, bob.clone()).unwrap();
+
+    // A second withdrawal is rejected, so the revealed preimage cannot be overwritten
+    let err = withdraw_funds(&mut test, contract, preimage, bob).unwrap_err();
+    assert!(err.to_string().contains("The funds were already withdrawn"));
+    let revealed_preimage = get_preimage(&mut test, contract).unwrap();
+    assert_eq!(revealed_preimage, Some(preimage));
+}
+
+#[test]
+fn it_does_not_allow_double_refunds() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+
+    let timelock = 10u64;
+    let contract = create_lock_contract(&mut test, alice.clone(), bob, timelock);
+
+    test.template_test.set_virtual_substate(
+        VirtualSubstateAddress::CurrentEpoch,
+        VirtualSubstate::CurrentEpoch(timelock + 1),
+    );
+    refund(&mut test, contract, alice.clone()).unwrap();
+
+    let err = refund(&mut test, contract, alice).unwrap_err();
+    assert!(err.to_string().contains("The funds were already refunded"));
+}
+
+#[test]
+fn it_emits_events_for_indexers() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+    let preimage = test.preimage;
+
+    let timelock = 10u64;
+    let result = try_create_lock_contract(&mut test, alice.clone(), bob.clone(), timelock).unwrap();
+    let contract = result.finalize.execution_results[2].decode().unwrap();
+    let events = decode_events(&result.finalize.events).unwrap();
+    assert_eq!(
+        events,
+        vec![TariEvent::HtlcCreated {
+            sender_public_key: public_key(&alice),
+            receiver_public_key: public_key(&bob),
+            resource_address: *CONFIDENTIAL_TARI_RESOURCE_ADDRESS,
+            amount: test.amount,
+            confidential: false,
+            hashlock: test.hashlock.into_array(),
+            timelock,
+        }]
+    );
+
+    // the preimage is included in the withdrawal event, so watchers do not need to read the component
+    let result = withdraw_funds(&mut test, contract, preimage, bob).unwrap();
+    let events = decode_events(&result.finalize.events).unwrap();
+    assert_eq!(
+        events,
+        vec![TariEvent::HtlcWithdrawn {
+            component_address: contract,
+            preimage,
+        }]
+    );
+}
+
+#[test]
+fn it_emits_an_event_for_refunds() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+
+    let timelock = 10u64;
+    let contract = create_lock_contract(&mut test, alice.clone(), bob, timelock);
+    test.template_test.set_virtual_substate(
+        VirtualSubstateAddress::CurrentEpoch,
+        VirtualSubstate::CurrentEpoch(timelock + 1),
+    );
+
+    let result = refund(&mut test, contract, alice).unwrap();
+    let events = decode_events(&result.finalize.events).unwrap();
+    assert_eq!(
+        events,
+        vec![TariEvent::HtlcRefunded {
+            component_address: contract
+        }]
+    );
+}
+
+#[test]
+fn the_created_event_does_not_reveal_confidential_amounts() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+
+    let result = try_lock_confidential(&mut test, alice, bob, 10).unwrap();
+    let events = decode_events(&result.finalize.events).unwrap();
+    match &events[..] {
+        [TariEvent::HtlcCreated {
+            amount,
+            confidential,
+            ..
+        }] => {
+            assert_eq!(*amount, Amount::zero());
+            assert!(*confidential);
+        }
+        _ => panic!("Unexpected events: {:?}", events),
+    }
+}
+
+#[test]
+fn it_derives_the_contract_address_from_the_sender_and_hashlock() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+
+    let contract = create_lock_contract(&mut test, alice.clone(), bob, 10);
+
+    // so the liquidity providers can check that the contract comes from this template
+    let sender_public_key = alice.owner_token.to_public_key().unwrap();
+    let expected_address = swap_component_address(
+        &test.atomic_swap_template,
+        &sender_public_key,
+        &test.hashlock.into_array(),
+    );
+    assert_eq!(contract, expected_address);
+}
+
+#[test]
+fn it_does_not_allow_reusing_a_hashlock_from_the_same_sender() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+
+    create_lock_contract(&mut test, alice.clone(), bob.clone(), 10);
+    assert!(try_create_lock_contract(&mut test, alice, bob.clone(), 10).is_err());
+
+    // other senders can still use it
+    let alice = test.alice.clone();
+    create_lock_contract(&mut test, bob, alice, 10);
+}
+
+#[test]
+fn it_swaps_other_fungible_resources() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+    let preimage = test.preimage;
+
+    let resource_address = mint_fungible(&mut test, Amount(1000), &alice);
+    let contract = lock_fungible(&mut test, resource_address, alice, bob.clone(), 10);
+
+    let state = get_state(&mut test, contract).unwrap();
+    assert_eq!(state.resource_address, resource_address);
+    assert_eq!(state.amount, test.amount);
+    assert!(state.non_fungible_ids.is_empty());
+
+    withdraw_funds(&mut test, contract, preimage, bob.clone()).unwrap();
+    assert_eq!(get_balance(&mut test, &bob, resource_address), test.amount);
+}
+
+#[test]
+fn it_swaps_non_fungible_tokens() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+    let preimage = test.preimage;
+
+    let id = NonFungibleId::from_u64(1);
+    let resource_address = mint_non_fungible(&mut test, id.clone(), &alice);
+    let contract = lock_non_fungible(
+        &mut test,
+        resource_address,
+        id.clone(),
+        alice.clone(),
+        bob.clone(),
+        10,
+    );
+
+    let state = get_state(&mut test, contract).unwrap();
+    assert_eq!(state.resource_address, resource_address);
+    assert_eq!(state.amount, Amount(1));
+    assert_eq!(state.non_fungible_ids, vec![id]);
+    assert_eq!(get_balance(&mut test, &alice, resource_address), Amount(0));
+
+    withdraw_funds(&mut test, contract, preimage, bob.clone()).unwrap();
+    assert_eq!(get_balance(&mut test, &bob, resource_address), Amount(1));
+}
+
+#[test]
+fn it_refunds_non_fungible_tokens() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+
+    let id = NonFungibleId::from_u64(1);
+    let resource_address = mint_non_fungible(&mut test, id.clone(), &alice);
+    let timelock = 10u64;
+    let contract = lock_non_fungible(
+        &mut test,
+        resource_address,
+        id,
+        alice.clone(),
+        bob,
+        timelock,
+    );
+
+    test.template_test.set_virtual_substate(
+        VirtualSubstateAddress::CurrentEpoch,
+        VirtualSubstate::CurrentEpoch(timelock + 1),
+    );
+    refund(&mut test, contract, alice.clone()).unwrap();
+    assert_eq!(get_balance(&mut test, &alice, resource_address), Amount(1));
+}
+
+#[test]
+fn it_rejects_contracts_of_a_mismatched_resource() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+
+    let resource_address = mint_fungible(&mut test, Amount(1000), &alice);
+    let id = NonFungibleId::from_u64(1);
+    let nft_address = mint_non_fungible(&mut test, id.clone(), &alice);
+    let contract = lock_fungible(&mut test, resource_address, alice, bob, 10);
+    let contract: TariContract = get_state(&mut test, contract).unwrap().try_into().unwrap();
+
+    TariResource::Fungible(resource_address)
+        .check_contract(&contract)
+        .unwrap();
+    // the liquidity providers expecting Tari must not accept any other resource
+    let err = TariResource::Fungible(*CONFIDENTIAL_TARI_RESOURCE_ADDRESS)
+        .check_contract(&contract)
+        .unwrap_err();
+    assert!(matches!(err, HtlcError::InvalidAsset { .. }));
+    let err = TariResource::NonFungible(nft_address, id)
+        .check_contract(&contract)
+        .unwrap_err();
+    assert!(matches!(err, HtlcError::InvalidAsset { .. }));
+}
+
+#[test]
+fn it_rejects_contracts_of_a_mismatched_token() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+
+    let id = NonFungibleId::from_u64(1);
+    let resource_address = mint_non_fungible(&mut test, id.clone(), &alice);
+    let contract = lock_non_fungible(&mut test, resource_address, id.clone(), alice, bob, 10);
+    let contract: TariContract = get_state(&mut test, contract).unwrap().try_into().unwrap();
+
+    TariResource::NonFungible(resource_address, id)
+        .check_contract(&contract)
+        .unwrap();
+    let err = TariResource::NonFungible(resource_address, NonFungibleId::from_u64(2))
+        .check_contract(&contract)
+        .unwrap_err();
+    assert!(matches!(err, HtlcError::InvalidAsset { .. }));
+}
+
+// the clients can send us the address of any component as their contract
+#[test]
+fn it_rejects_the_state_of_other_components() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+
+    // an account does not return a contract state
+    let result = test
+        .template_test
+        .execute_and_commit(
+            vec![Instruction::CallMethod {
+                component_address: alice.account_address,
+                method: "balance".to_string(),
+                args: args![*CONFIDENTIAL_TARI_RESOURCE_ADDRESS],
+            }],
+            vec![],
+        )
+        .unwrap();
+    let err = decode_result::<HashedTimelockState>(&result, "get_state").unwrap_err();
+    assert!(matches!(err, TariError::InvalidResult { .. }));
+
+    // and a look-alike template could return any bytes as the public keys
+    let contract = create_lock_contract(&mut test, alice, bob, 10);
+    let mut state = get_state(&mut test, contract).unwrap();
+    state.sender_public_key = RistrettoPublicKeyBytes::from_bytes(&[0xff; 32]).unwrap();
+    let err = TariContract::try_from(state).unwrap_err();
+    assert!(matches!(err, TariError::InvalidPublicKey { .. }));
+}
+
+#[test]
+fn it_locks_confidential_funds() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+    let preimage = test.preimage;
+
+    let contract = lock_confidential(&mut test, alice, bob.clone(), 10);
+
+    // the amount is hidden, so the receiver must check the vault with the view key or the output mask
+    let state = get_state(&mut test, contract).unwrap();
+    assert!(state.confidential);
+    assert_eq!(state.amount, Amount::zero());
+    assert_eq!(state.status, HashedTimelockStatus::Locked);
+
+    withdraw_funds(&mut test, contract, preimage, bob).unwrap();
+    let state = get_state(&mut test, contract).unwrap();
+    assert_eq!(state.status, HashedTimelockStatus::Withdrawn);
+    assert_eq!(state.preimage, Some(preimage));
+}
+
+#[test]
+fn it_refunds_confidential_funds() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+
+    let timelock = 10u64;
+    let contract = lock_confidential(&mut test, alice.clone(), bob, timelock);
+
+    test.template_test.set_virtual_substate(
+        VirtualSubstateAddress::CurrentEpoch,
+        VirtualSubstate::CurrentEpoch(timelock + 1),
+    );
+    refund(&mut test, contract, alice).unwrap();
+    let state = get_state(&mut test, contract).unwrap();
+    assert_eq!(state.status, HashedTimelockStatus::Refunded);
+}
+
+#[test]
+fn revealed_funds_are_not_confidential() {
+    let mut test = setup();
+    let alice = test.alice.clone();
+    let bob = test.bob.clone();
+
+    let contract = create_lock_contract(&mut test, alice, bob, 10);
+    let state = get_state(&mut test, contract).unwrap();
+    assert!(!state.confidential);
+}
@@ -1,3 +1,5 @@
+use tari::events::{decode_events, TariEvent};
+use tari_engine_types::commit_result::ExecuteResult;
 use tari_engine_types::instruction::Instruction;
 use tari_template_lib::{
     args,
@@ -75,7 +77,7 @@ fn create_lp_index_component(
     result.finalize.execution_results[0].decode().unwrap()
 }
 
-fn register_lp(test: &mut LpTest, network_address: String) {
+fn register_lp(test: &mut LpTest, network_address: String) -> ExecuteResult {
     let (_, owner_token, _) = test.template_test.create_owned_account();
     let result = test
         .template_test
@@ -98,9 +100,10 @@ fn register_lp(test: &mut LpTest, network_address: String) {
     };
 
     test.users.push(user);
+    result
 }
 
-fn add_position(test: &mut LpTest, user_index: usize, position: Position) {
+fn add_position(test: &mut LpTest, user_index: usize, position: Position) -> ExecuteResult {
     let user = test.users[user_index].clone();
     test.template_test
         .execute_and_commit(
@@ -111,10 +114,10 @@ fn add_position(test: &mut LpTest, user_index: usize, position: Position) {
             }],
             vec![user.owner_token],
         )
-        .unwrap();
+        .unwrap()
 }
 
-fn remove_position(test: &mut LpTest, user_index: usize, position_index: usize) {
+fn remove_position(test: &mut LpTest, user_index: usize, position_index: usize) -> ExecuteResult {
     let user = test.users[user_index].clone();
     test.template_test
         .execute_and_commit(
@@ -125,7 +128,7 @@ fn remove_position(test: &mut LpTest, user_index: usize, position_index: usize)
             }],
             vec![user.owner_token],
         )
-        .unwrap();
+        .unwrap()
 }
 
 fn get_positions(test: &mut LpTest, user_index: usize) -> Vec<Position> {
@@ -144,7 +147,11 @@ fn get_positions(test: &mut LpTest, user_index: usize) -> Vec<Position> {
     result.finalize.execution_results[0].decode().unwrap()
 }
 
-fn replace_positions(test: &mut LpTest, user_index: usize, positions: Vec<Position>) {
+fn replace_positions(
+    test: &mut LpTest,
+    user_index: usize,
+    positions: Vec<Position>,
+) -> ExecuteResult {
     let user = test.users[user_index].clone();
     test.template_test
         .execute_and_commit(
@@ -155,7 +162,25 @@ fn replace_positions(test: &mut LpTest, user_index: usize, positions: Vec<Positi
             }],
             vec![user.owner_token],
         )
-        .unwrap();
+        .unwrap()
+}
+
+fn set_network_address(
+    test: &mut LpTest,
+    user_index: usize,
+    network_address: String,
+) -> ExecuteResult {
+    let user = test.users[user_index].clone();
+    test.template_test
+        .execute_and_commit(
+            vec![Instruction::CallMethod {
+                component_address: user.position_component,
+                method: "set_network_address".to_string(),
+                args: args![network_address],
+            }],
+            vec![user.owner_token],
+        )
+        .unwrap()
 }
 
 fn get_all_provider_positions(test: &mut LpTest) -> Vec<ProviderPosition> {
@@ -324,3 +349,55 @@ fn it_does_get_all_positions() {
         20001
     );
 }
+
+#[test]
+fn it_emits_events_for_indexers() {
+    let mut test = setup();
+    let result = register_lp(&mut test, "http://alice".to_owned());
+    let alice = test.users[0].clone();
+
+    // the watchers find the new providers through the index
+    let events = decode_events(&result.finalize.events).unwrap();
+    assert_eq!(
+        events,
+        vec![TariEvent::ProviderRegistered {
+            owner_token: alice.owner_token.to_string(),
+            position_component: alice.position_component,
+        }]
+    );
+
+    let position = Position {
+        provided_token: "tari".to_string(),
+        provided_token_balance: 100000,
+        requested_token: "eth_wei".to_string(),
+        requested_token_balance: 20000,
+    };
+    let positions_changed = |position_count| {
+        vec![TariEvent::PositionsChanged {
+            component_address: alice.position_component,
+            position_count,
+        }]
+    };
+
+    let result = add_position(&mut test, 0, position.clone());
+    let events = decode_events(&result.finalize.events).unwrap();
+    assert_eq!(events, positions_changed(1));
+
+    let result = replace_positions(&mut test, 0, vec![position.clone(), position]);
+    let events = decode_events(&result.finalize.events).unwrap();
+    assert_eq!(events, positions_changed(2));
+
+    let result = remove_position(&mut test, 0, 0);
+    let events = decode_events(&result.finalize.events).unwrap();
+    assert_eq!(events, positions_changed(1));
+
+    let result = set_network_address(&mut test, 0, "http://alice.new".to_owned());
+    let events = decode_events(&result.finalize.events).unwrap();
+    assert_eq!(
+        events,
+        vec![TariEvent::NetworkAddressChanged {
+            component_address: alice.position_component,
+            network_address: "http://alice.new".to_string(),
+        }]
+    );
+}
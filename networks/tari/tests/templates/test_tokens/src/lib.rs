@@ -0,0 +1,36 @@
+use tari_template_lib::models::ConfidentialOutputProof;
+use tari_template_lib::prelude::*;
+
+// Only used in the tests, to lock resources other than Tari in the swap contracts
+#[template]
+mod test_tokens_template {
+    use super::*;
+
+    pub struct TestTokens {}
+
+    impl TestTokens {
+        // a new fungible resource, with all the supply in the returned bucket
+        pub fn mint_fungible(supply: Amount) -> Bucket {
+            ResourceBuilder::fungible()
+                .with_token_symbol("TEST")
+                .initial_supply(supply)
+                .build_bucket()
+        }
+
+        // a new confidential resource, with the hidden supply of the proof in the returned bucket
+        pub fn mint_confidential(supply: ConfidentialOutputProof) -> Bucket {
+            ResourceBuilder::confidential()
+                .with_token_symbol("TEST_CONFIDENTIAL")
+                .initial_supply(supply)
+                .build_bucket()
+        }
+
+        // a new non-fungible resource with a single token
+        pub fn mint_non_fungible(id: NonFungibleId) -> Bucket {
+            ResourceBuilder::non_fungible()
+                .with_token_symbol("TEST_NFT")
+                .with_non_fungible(id, &(), &())
+                .build_bucket()
+        }
+    }
+}